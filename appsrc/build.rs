@@ -7,12 +7,18 @@
 //! Cargo re-run the build script whenever `memory.x` is changed,
 //! updating `memory.x` ensures a rebuild of the application with the
 //! new memory settings.
+//!
+//! It also generates the thermistor ADC -> temperature conversion table
+//! from the NTC parameters in `thermistor.toml`.
 
 use std::env;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::Write;
 use std::path::PathBuf;
 
+// Hand-made table of the default circuit, kept as reference of the generator.
+include!("util/temperature_table.rs");
+
 fn main() {
     // Put `memory.x` in our output directory and ensure it's
     // on the linker search path.
@@ -33,4 +39,188 @@ fn main() {
     println!("cargo:rustc-link-arg-bins=-Tlink.x");
     println!("cargo:rustc-link-arg-bins=-Tlink-rp.x");
     println!("cargo:rustc-link-arg-bins=-Tdefmt.x");
+
+    // Generate thermistor conversion table.
+    println!("cargo:rerun-if-changed=thermistor.toml");
+    let config = fs::read_to_string("thermistor.toml").expect("thermistor.toml not found");
+    let params = ThermistorParams::parse(&config).unwrap_or_else(|e| panic!("thermistor.toml: {}", e));
+    println!("cargo:rerun-if-changed=util/temperature_table.rs");
+    if params.is_reference_circuit() {
+        params.check_reference_table().unwrap_or_else(|e| panic!("thermistor.toml: {}", e));
+    }
+    File::create(out.join("temperature_table.rs"))
+        .unwrap()
+        .write_all(params.generate_table_source().as_bytes())
+        .unwrap();
+}
+
+enum Model
+{
+    Beta { r25: f64, beta: f64 },
+    SteinhartHart { a: f64, b: f64, c: f64 },
+}
+
+struct ThermistorParams
+{
+    model: Model,
+    series_resistor: f64,
+    divider_supply_voltage: f64,
+    adc_reference_voltage: f64,
+    adc_resolution_bits: u32,
+    table_step: usize,
+}
+
+impl ThermistorParams
+{
+    // Accept "key = value" lines, '#' starts comment.
+    fn parse(config: &str) -> Result<Self, String>
+    {
+        let value = |key: &str| -> Option<&str> {
+            config.lines()
+                .map(|line| line.split('#').next().unwrap_or("").trim())
+                .filter_map(|line| line.split_once('='))
+                .find(|(k, _)| k.trim() == key)
+                .map(|(_, v)| v.trim().trim_matches('"'))
+        };
+        let number = |key: &str| -> Result<f64, String> {
+            value(key).ok_or(format!("{} is not found", key))?
+                .parse::<f64>().map_err(|e| format!("{}: {}", key, e))
+        };
+
+        let model = match value("model").unwrap_or("beta") {
+            "beta" => Model::Beta { r25: number("r25")?, beta: number("beta")? },
+            "steinhart-hart" => Model::SteinhartHart { a: number("sh_a")?, b: number("sh_b")?, c: number("sh_c")? },
+            m => return Err(format!("unknown model: {}", m)),
+        };
+
+        Ok(Self {
+            model: model,
+            series_resistor: number("series_resistor")?,
+            divider_supply_voltage: number("divider_supply_voltage")?,
+            adc_reference_voltage: number("adc_reference_voltage")?,
+            adc_resolution_bits: number("adc_resolution_bits")? as u32,
+            table_step: number("table_step")? as usize,
+        })
+    }
+
+    // Circuit which util/temperature_table.rs was made for.
+    fn is_reference_circuit(&self) -> bool
+    {
+        matches!(self.model, Model::Beta { r25, beta } if r25 == 10000.0 && beta == 3380.0)
+            && self.series_resistor == 6300.0
+            && self.divider_supply_voltage == 3.3
+            && self.adc_reference_voltage == 3.3
+            && self.adc_resolution_bits == 12
+            && self.table_step == 10
+    }
+
+    // Generated table must equal hand-made table record by record.
+    fn check_reference_table(&self) -> Result<(), String>
+    {
+        let table = self.generate_table();
+        if table.len() != TEMPERATURE_TABLE.len() {
+            return Err(format!("generated table has {} records, reference has {}", table.len(), TEMPERATURE_TABLE.len()));
+        }
+        match table.iter().zip(TEMPERATURE_TABLE.iter()).position(|(g, r)| g != r) {
+            Some(i) => Err(format!("generated table record {} is {}, reference is {}", i, table[i], TEMPERATURE_TABLE[i])),
+            None => Ok(()),
+        }
+    }
+
+    fn adc_full_scale(&self) -> usize
+    {
+        1 << self.adc_resolution_bits
+    }
+
+    // Temperature[Celsius] at ADC count, None if out of circuit range.
+    fn temperature(&self, adc_value: usize) -> Option<f64>
+    {
+        let voltage = adc_value as f64 * self.adc_reference_voltage / self.adc_full_scale() as f64;
+        if voltage <= 0.0 || voltage >= self.divider_supply_voltage {
+            return None;
+        }
+        let resistance = self.series_resistor * (self.divider_supply_voltage / voltage - 1.0);
+
+        let inv_kelvin = match self.model {
+            Model::Beta { r25, beta } => 1.0 / 298.15 + (resistance / r25).ln() / beta,
+            Model::SteinhartHart { a, b, c } => {
+                let ln_r = resistance.ln();
+                a + b * ln_r + c * ln_r * ln_r * ln_r
+            }
+        };
+        Some(1.0 / inv_kelvin - 273.15)
+    }
+
+    fn generate_table(&self) -> Vec<i16>
+    {
+        // Last record is used as interpolation end point of max ADC value.
+        let len = (self.adc_full_scale() - 1) / self.table_step + 2;
+        let raw: Vec<Option<f64>> = (0..len).map(|i| self.temperature(i * self.table_step)).collect();
+
+        // Out of range records copy nearest valid record.
+        let first_valid = raw.iter().position(|t| t.is_some()).expect("No valid table record");
+        let mut last = raw[first_valid].unwrap();
+        raw.iter().map(|t| {
+            last = t.unwrap_or(last);
+            (last * 100.0).round().clamp(i16::MIN as f64, i16::MAX as f64) as i16
+        }).collect()
+    }
+
+    fn generate_table_source(&self) -> String
+    {
+        let table = self.generate_table();
+        let mut src = String::new();
+
+        src.push_str("// Generated by build.rs from thermistor.toml. Do not edit.\n");
+        src.push_str(&format!("const TEMPERATURE_TABLE_STEP : usize = {};\n", self.table_step));
+        src.push_str(&format!("const ADC_FULL_SCALE : usize = {};\n", self.adc_full_scale()));
+        src.push_str(&format!("static TEMPERATURE_TABLE : [i16; {}] = [\n", table.len()));
+        for record in table.chunks(10) {
+            let line: Vec<String> = record.iter().map(|t| format!("{},", t)).collect();
+            src.push_str(&format!("    {}\n", line.join(" ")));
+        }
+        src.push_str("];\n");
+
+        // Same parameters for runtime formula conversion.
+        src.push_str(&format!(
+            "const THERMISTOR_CIRCUIT : DividerCircuit = DividerCircuit {{ series_resistor: {:?}, supply_voltage: {:?}, adc_reference_voltage: {:?}, adc_full_scale: {:?} }};\n",
+            self.series_resistor as f32, self.divider_supply_voltage as f32, self.adc_reference_voltage as f32, self.adc_full_scale() as f32
+        ));
+        src.push_str(&match self.model {
+            Model::Beta { r25, beta } => format!(
+                "const THERMISTOR_FORMULA : Conversion = Conversion::Beta {{ r25: {:?}, beta: {:?} }};\n", r25 as f32, beta as f32
+            ),
+            Model::SteinhartHart { a, b, c } => format!(
+                "const THERMISTOR_FORMULA : Conversion = Conversion::SteinhartHart {{ a: {:?}, b: {:?}, c: {:?} }};\n", a as f32, b as f32, c as f32
+            ),
+        });
+
+        src
+    }
+}
+
+// Firmware build runs check_reference_table() in main(). These run with "cargo test" in test/host.
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn default_parameters_are_reference_circuit()
+    {
+        let params = ThermistorParams::parse(include_str!("thermistor.toml")).unwrap();
+
+        assert!(params.is_reference_circuit());
+        assert_eq!(params.check_reference_table(), Ok(()));
+    }
+
+    #[test]
+    fn changed_table_step_is_not_reference_circuit()
+    {
+        let config = include_str!("thermistor.toml").replace("table_step = 10", "table_step = 20");
+        let params = ThermistorParams::parse(&config).unwrap();
+
+        assert!(!params.is_reference_circuit());
+        assert!(params.check_reference_table().is_err());
+    }
 }
@@ -0,0 +1,299 @@
+use core::sync::atomic::{compiler_fence, Ordering};
+use num_traits::float::Float;
+
+use embassy_rp::adc::Adc;
+use embassy_rp::pac;
+use embassy_rp::peripherals::DMA_CH1;
+
+//
+// static const variables
+//
+
+// ADC input number of each sampled channel, in round-robin order.
+// GPIO26 = AIN0(heater1 thermistor), GPIO28 = AIN2(heater1 current), AIN4 = internal temperature sensor
+pub const ADC_SAMPLED_INPUTS : [u8; ADC_SAMPLED_CHANNEL_NUM] = [0, 2, 4];
+pub const ADC_SAMPLED_CHANNEL_NUM : usize = 3;
+pub const ADC_SAMPLED_NAMES : [&str; ADC_SAMPLED_CHANNEL_NUM] = ["heater1", "heater1_current", "cpu"];
+const ADC_TEMPERATURE_SENSOR_INPUT : u8 = 4;
+
+// ADC clock(48MHz) / sample rate. Total sample rate of all channels.
+const ADC_CLOCK_HZ : u32 = 48_000_000;
+const ADC_SAMPLE_RATE_HZ : u32 = 30_000;
+
+// DMA writes ADC FIFO into ring buffer. Ring must be naturally aligned to its size in bytes.
+// DMA ring size is power of 2, so it can not be multiple of channel number. Channel is decided by
+// absolute sample index, not ring position. 8192 samples hold about 270ms at 30ksps, so thermometer tick
+// delayed by flash write does not lose samples.
+const SAMPLE_RING_LEN : usize = 8192;
+const SAMPLE_RING_SIZE_BITS : u8 = 14;   // 8192 samples * 2 bytes = 2^14 bytes
+const _: () = assert!(SAMPLE_RING_LEN * 2 == 1 << SAMPLE_RING_SIZE_BITS);
+// Oldest samples in ring may be overwritten by DMA while collect() reads them, they are dropped.
+const SAMPLE_RING_GUARD_LEN : usize = 512;
+const DMA_CHANNEL : usize = 1;
+const DMA_TREQ_ADC : u8 = 36;
+const DMA_TRANSFER_COUNT : u32 = u32::MAX;
+// Restart transfer before DMA transfer count runs out (about 39 hours at 30ksps).
+const DMA_RESTART_REMAINING : u32 = ADC_SAMPLE_RATE_HZ * 60;
+
+// Samples this close to ADC range ends are treated as saturated.
+pub const ADC_SATURATION_MARGIN : u16 = 8;
+pub const ADC_MAX_VALUE : u16 = 4095;
+
+#[repr(C, align(16384))]
+struct SampleRing([u16; SAMPLE_RING_LEN]);
+
+//
+// static variables
+//
+static mut SAMPLE_RING : SampleRing = SampleRing([0; SAMPLE_RING_LEN]);
+
+// Block statistics of one channel, since last collect().
+#[derive(Copy, Clone)]
+pub struct SampleBlock
+{
+    pub count : u32,
+    pub sum : u32,
+    pub sum_sq : u64,
+    pub min : u16,
+    pub max : u16,
+    // Latest samples selected by oversampling of collect().
+    pub latest_count : u32,
+    pub latest_sum : u32,
+}
+
+impl SampleBlock
+{
+    const fn new() -> Self
+    {
+        Self { count: 0, sum: 0, sum_sq: 0, min: u16::MAX, max: 0, latest_count: 0, latest_sum: 0 }
+    }
+
+    fn add(&mut self, sample: u16)
+    {
+        self.count += 1;
+        self.sum += sample as u32;
+        self.sum_sq += (sample as u64) * (sample as u64);
+        self.min = self.min.min(sample);
+        self.max = self.max.max(sample);
+    }
+
+    fn add_latest(&mut self, sample: u16)
+    {
+        self.latest_count += 1;
+        self.latest_sum += sample as u32;
+    }
+
+    // Rounded block average, None if no sample arrived in this block.
+    pub fn average(&self) -> Option<u16>
+    {
+        if self.count == 0 {
+            return None;
+        }
+        Some(((self.sum + self.count / 2) / self.count) as u16)
+    }
+
+    // Rounded average of latest samples, None if no sample arrived in this block.
+    pub fn oversampled(&self) -> Option<u16>
+    {
+        if self.latest_count == 0 {
+            return None;
+        }
+        Some(((self.latest_sum + self.latest_count / 2) / self.latest_count) as u16)
+    }
+}
+
+// RP2040 ADC in free-running round-robin mode, sampled into RAM by DMA.
+pub struct AdcDma<'a>
+{
+    // Keep ADC driver, ADC is reset and powered up by it.
+    _adc : Adc<'a>,
+    _dma : DMA_CH1,
+    consumed : u32,
+}
+
+impl<'a> AdcDma<'a>
+{
+    pub fn new(adc: Adc<'a>, dma: DMA_CH1) -> Self
+    {
+        // Disable digital input of analog pins. GPIO26 + AIN number.
+        for input in ADC_SAMPLED_INPUTS.iter().filter(|i| **i != ADC_TEMPERATURE_SENSOR_INPUT) {
+            pac::PADS_BANK0.gpio(26 + *input as usize).modify(|w| {
+                w.set_ie(false);
+                w.set_od(true);
+                w.set_pue(false);
+                w.set_pde(false);
+            });
+        }
+
+        let mut adc_dma = Self {
+            _adc: adc,
+            _dma: dma,
+            consumed: 0,
+        };
+        adc_dma.start();
+
+        adc_dma
+    }
+
+    fn start(&mut self)
+    {
+        let rrobin_mask = ADC_SAMPLED_INPUTS.iter().fold(0u8, |mask, input| mask | (1 << input));
+        let ring_addr = unsafe { SAMPLE_RING.0.as_ptr() as u32 };
+
+        // FIFO: 12bit samples, DREQ when at least 1 sample.
+        pac::ADC.fcs().write(|w| {
+            w.set_en(true);
+            w.set_dreq_en(true);
+            w.set_thresh(1);
+            w.set_err(false);
+            w.set_shift(false);
+        });
+        pac::ADC.div().write(|w| {
+            w.set_int((ADC_CLOCK_HZ / ADC_SAMPLE_RATE_HZ - 1) as u16);
+            w.set_frac(0);
+        });
+
+        let ch = pac::DMA.ch(DMA_CHANNEL);
+        ch.read_addr().write_value(pac::ADC.fifo().as_ptr() as u32);
+        ch.write_addr().write_value(ring_addr);
+        ch.trans_count().write_value(DMA_TRANSFER_COUNT);
+        compiler_fence(Ordering::SeqCst);
+        ch.ctrl_trig().write(|w| {
+            w.set_treq_sel(pac::dma::vals::TreqSel(DMA_TREQ_ADC));
+            w.set_data_size(pac::dma::vals::DataSize::SIZE_HALFWORD);
+            w.set_incr_read(false);
+            w.set_incr_write(true);
+            w.set_ring_sel(true);   // wrap write address
+            w.set_ring_size(SAMPLE_RING_SIZE_BITS);
+            w.set_chain_to(DMA_CHANNEL as u8);
+            w.set_en(true);
+        });
+        compiler_fence(Ordering::SeqCst);
+
+        // Start from first input, so sample index decides channel.
+        pac::ADC.cs().write(|w| {
+            w.set_en(true);
+            w.set_ts_en(true);
+            w.set_ainsel(ADC_SAMPLED_INPUTS[0]);
+            w.set_rrobin(rrobin_mask);
+            w.set_start_many(true);
+        });
+        self.consumed = 0;
+    }
+
+    fn stop(&mut self)
+    {
+        pac::ADC.cs().modify(|w| w.set_start_many(false));
+        while !pac::ADC.cs().read().ready() {}
+
+        pac::DMA.chan_abort().write(|w| w.set_chan_abort(1 << DMA_CHANNEL));
+        while pac::DMA.ch(DMA_CHANNEL).ctrl_trig().read().busy() {}
+
+        // Drain FIFO
+        while pac::ADC.fcs().read().level() > 0 {
+            let _ = pac::ADC.fifo().read();
+        }
+    }
+
+    // Collect samples written since last call, per channel in ADC_SAMPLED_INPUTS order.
+    // oversampling is number of latest samples of each channel averaged by SampleBlock::oversampled().
+    pub fn collect(&mut self, oversampling: [usize; ADC_SAMPLED_CHANNEL_NUM]) -> [SampleBlock; ADC_SAMPLED_CHANNEL_NUM]
+    {
+        let mut blocks = [SampleBlock::new(); ADC_SAMPLED_CHANNEL_NUM];
+
+        let remaining = pac::DMA.ch(DMA_CHANNEL).trans_count().read();
+        let transferred = DMA_TRANSFER_COUNT - remaining;
+        // Samples older than ring length are already overwritten, and ones near write pointer may be overwritten during read.
+        let first = transferred.saturating_sub((SAMPLE_RING_LEN - SAMPLE_RING_GUARD_LEN) as u32).max(self.consumed);
+
+        compiler_fence(Ordering::SeqCst);
+        for index in first..transferred {
+            let sample = unsafe {
+                core::ptr::read_volatile(&SAMPLE_RING.0[index as usize % SAMPLE_RING_LEN])
+            };
+            let channel = index as usize % ADC_SAMPLED_CHANNEL_NUM;
+            blocks[channel].add(sample & 0x0FFF);
+            // Round-robin, so last N * channel number samples hold latest N samples of each channel.
+            if ((transferred - index) as usize) <= oversampling[channel] * ADC_SAMPLED_CHANNEL_NUM {
+                blocks[channel].add_latest(sample & 0x0FFF);
+            }
+        }
+        self.consumed = transferred;
+
+        if remaining < DMA_RESTART_REMAINING {
+            self.stop();
+            self.start();
+        }
+
+        blocks
+    }
+}
+
+// Raw ADC statistics of one channel over last N ticks.
+#[derive(Copy, Clone)]
+pub struct RawSummary
+{
+    pub latest : Option<u16>,
+    pub count : u32,
+    pub min : u16,
+    pub max : u16,
+    pub mean : f32,
+    pub stddev : f32,
+    pub saturated_low : bool,
+    pub saturated_high : bool,
+}
+
+impl RawSummary
+{
+    pub const fn new() -> Self
+    {
+        Self { latest: None, count: 0, min: 0, max: 0, mean: 0.0, stddev: 0.0, saturated_low: false, saturated_high: false }
+    }
+}
+
+pub struct RawWindow<const N: usize>
+{
+    blocks : [SampleBlock; N],
+    pos : usize,
+}
+
+impl<const N: usize> RawWindow<N>
+{
+    pub fn new() -> Self
+    {
+        Self { blocks: [SampleBlock::new(); N], pos: 0 }
+    }
+
+    pub fn push(&mut self, block: SampleBlock)
+    {
+        self.blocks[self.pos] = block;
+        self.pos = (self.pos + 1) % N;
+    }
+
+    pub fn summary(&self) -> RawSummary
+    {
+        let latest = self.blocks[(self.pos + N - 1) % N].average();
+        let (count, sum, sum_sq, min, max) = self.blocks.iter().fold((0u32, 0u64, 0u64, u16::MAX, 0u16), |(c, s, sq, mn, mx), b| {
+            (c + b.count, s + b.sum as u64, sq + b.sum_sq, mn.min(b.min), mx.max(b.max))
+        });
+        if count == 0 {
+            return RawSummary::new();
+        }
+
+        let mean = sum as f32 / count as f32;
+        // E[x^2] - E[x]^2 cancels catastrophically in f32, so calculate numerator exactly in integer.
+        let numerator = (count as u128 * sum_sq as u128).saturating_sub(sum as u128 * sum as u128);
+        let variance = (numerator as f64 / (count as f64 * count as f64)) as f32;
+
+        RawSummary {
+            latest: latest,
+            count: count,
+            min: min,
+            max: max,
+            mean: mean,
+            stddev: variance.sqrt(),
+            saturated_low: min <= ADC_SATURATION_MARGIN,
+            saturated_high: max >= ADC_MAX_VALUE - ADC_SATURATION_MARGIN,
+        }
+    }
+}
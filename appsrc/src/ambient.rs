@@ -0,0 +1,53 @@
+use embassy_time::{Duration, Ticker, Timer};
+use embedded_hal_async::i2c::I2c;
+
+use crate::sht3x::*;
+use crate::thermometer::*;
+
+//
+// static const variables
+//
+const AMBIENT_TASK_TICK_MS : u64 = 1000;
+const AMBIENT_RETRY_INTERVAL_MS : u64 = 5000;
+
+async fn measure<I: I2c>(sensor: &mut Sht3x<I>) -> Result<AmbientReading, AmbientError<I::Error>>
+{
+    sensor.start_measurement().await?;
+    Timer::after(Duration::from_millis(SHT3X_MEASUREMENT_TIME_MS)).await;
+    sensor.read_measurement().await
+}
+
+#[embassy_executor::task]
+pub async fn ambient_task(i2c: embassy_rp::i2c::I2c<'static, embassy_rp::peripherals::I2C0, embassy_rp::i2c::Async>)
+{
+    let mut sensor = Sht3x::new(i2c, SHT3X_DEFAULT_ADDRESS);
+    let mut ticker = Ticker::every(Duration::from_millis(AMBIENT_TASK_TICK_MS));
+    let mut sensor_found = true;
+
+    loop {
+        match measure(&mut sensor).await {
+            Ok(reading) => {
+                if !sensor_found {
+                    log::info!("Ambient sensor found.");
+                    sensor_found = true;
+                }
+                set_ambient(Some(reading.temperature), Some(reading.humidity));
+                ticker.next().await;
+            }
+            Err(AmbientError::Crc) => {
+                // Keep last reading, a single corrupted frame is not a missing sensor.
+                log::warn!("Ambient sensor CRC error.");
+                ticker.next().await;
+            }
+            Err(AmbientError::Bus(e)) => {
+                // Sensor is not connected or not responding. Report no reading and retry later.
+                if sensor_found {
+                    log::warn!("Ambient sensor not responding: {:?}", e);
+                    sensor_found = false;
+                }
+                set_ambient(None, None);
+                Timer::after(Duration::from_millis(AMBIENT_RETRY_INTERVAL_MS)).await;
+            }
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use core::cell::RefCell;
+use num_traits::float::FloatCore;
+
+use alloc::string::String;
+use embassy_sync::blocking_mutex::{Mutex, raw::ThreadModeRawMutex};
+
+use crate::storage::*;
+use crate::thermometer::*;
+
+//
+// static const variables
+//
+
+// Two calibration points closer than this make gain too sensitive to noise.
+const CALIBRATION_MIN_POINT_DISTANCE_CELCIUS : f32 = 5.0;
+const CALIBRATION_GAIN_MIN : f32 = 0.8;
+const CALIBRATION_GAIN_MAX : f32 = 1.2;
+// Larger offset means wrong reference or broken sensor rather than sensor tolerance.
+const CALIBRATION_OFFSET_MAX_CELCIUS : f32 = 10.0;
+const CALIBRATION_RECORD_SIZE : usize = 8;
+// Pico ADC reference is 3.3V supply through RC filter. Measured value can be set to compensate it.
+pub const ADC_VREF_NOMINAL : f32 = 3.3;
+const ADC_VREF_MIN : f32 = 3.0;
+const ADC_VREF_MAX : f32 = 3.6;
+
+#[derive(Copy, Clone)]
+pub struct Calibration
+{
+    gain : f32,
+    offset : f32,
+    // (raw temperature, reference temperature) of first point, waiting for second point.
+    point1 : Option<(f32, f32)>,
+}
+
+//
+// static variables
+//
+static CALIBRATIONS : Mutex<ThreadModeRawMutex, RefCell<[Calibration; SENSOR_CHANNEL_NUM]>> = Mutex::new(RefCell::new([Calibration::new(); SENSOR_CHANNEL_NUM]));
+static ADC_VREF : Mutex<ThreadModeRawMutex, RefCell<f32>> = Mutex::new(RefCell::new(ADC_VREF_NOMINAL));
+
+impl Calibration
+{
+    pub const fn new() -> Self
+    {
+        Self {
+            gain: 1.0,
+            offset: 0.0,
+            point1: None,
+        }
+    }
+
+    // corrected = gain * raw + offset
+    pub fn apply(&self, raw_temperature: f32) -> f32
+    {
+        self.gain * raw_temperature + self.offset
+    }
+
+    pub fn gain(&self) -> f32 { self.gain }
+    pub fn offset(&self) -> f32 { self.offset }
+    pub fn point1(&self) -> Option<(f32, f32)> { self.point1 }
+}
+
+pub fn calibration(channel: SensorChannel) -> Calibration
+{
+    CALIBRATIONS.lock(|lock| {
+        lock.borrow_mut()[channel as usize]
+    })
+}
+
+// Record first point. raw_temperature is current uncorrected temperature of channel.
+pub fn calibrate_point1(channel: SensorChannel, raw_temperature: f32, reference: f32) -> Calibration
+{
+    CALIBRATIONS.lock(|lock| {
+        let mut calibrations = lock.borrow_mut();
+        calibrations[channel as usize].point1 = Some((raw_temperature, reference));
+        calibrations[channel as usize]
+    })
+}
+
+// Record second point, and calculate gain/offset from two points.
+// Functions below return error for invalid request only, call save_calibration() to persist result.
+pub fn calibrate_point2(channel: SensorChannel, raw_temperature: f32, reference: f32) -> Result<Calibration, String>
+{
+    let (raw1, reference1) = calibration(channel).point1.ok_or(String::from("point1 is not recorded"))?;
+
+    if (raw_temperature - raw1).abs() < CALIBRATION_MIN_POINT_DISTANCE_CELCIUS {
+        return Err(format!("point1 and point2 must be at least {:.1} celsius apart", CALIBRATION_MIN_POINT_DISTANCE_CELCIUS));
+    }
+    let gain = (reference - reference1) / (raw_temperature - raw1);
+    let offset = reference1 - gain * raw1;
+    check_calibration(gain, offset)?;
+
+    let result = CALIBRATIONS.lock(|lock| {
+        let mut calibrations = lock.borrow_mut();
+        calibrations[channel as usize] = Calibration { gain: gain, offset: offset, point1: None };
+        calibrations[channel as usize]
+    });
+
+    Ok(result)
+}
+
+// Single point calibration, correct offset only. Suitable for sensor with known slope like RP2040 internal sensor.
+pub fn calibrate_offset(channel: SensorChannel, raw_temperature: f32, reference: f32) -> Result<Calibration, String>
+{
+    // Offset only calibration would silently drop gain of two-point calibration.
+    if calibration(channel).gain != 1.0 {
+        return Err(String::from("channel has two-point calibration, reset it first"));
+    }
+    let offset = reference - raw_temperature;
+    check_calibration(1.0, offset)?;
+
+    let result = CALIBRATIONS.lock(|lock| {
+        let mut calibrations = lock.borrow_mut();
+        calibrations[channel as usize] = Calibration { gain: 1.0, offset: offset, point1: None };
+        calibrations[channel as usize]
+    });
+
+    Ok(result)
+}
+
+// Same limits for calculated and loaded calibration.
+// Offset of two-point calibration is extrapolated to 0 celsius, so only offset-only calibration has offset limit.
+fn check_calibration(gain: f32, offset: f32) -> Result<(), String>
+{
+    if !(gain >= CALIBRATION_GAIN_MIN && gain <= CALIBRATION_GAIN_MAX) {
+        return Err(format!("gain {:.3} is out of range", gain));
+    }
+    if !offset.is_finite() {
+        return Err(String::from("offset is not finite"));
+    }
+    if gain == 1.0 && !(offset.abs() <= CALIBRATION_OFFSET_MAX_CELCIUS) {
+        return Err(format!("offset {:.2} is out of range", offset));
+    }
+
+    Ok(())
+}
+
+pub fn reset_calibration(channel: SensorChannel) -> Calibration
+{
+    CALIBRATIONS.lock(|lock| {
+        lock.borrow_mut()[channel as usize] = Calibration::new();
+    });
+
+    Calibration::new()
+}
+
+pub fn load_calibration()
+{
+    let mut buf = [0u8; CALIBRATION_RECORD_SIZE * SENSOR_CHANNEL_NUM];
+    match storage_read(StorageSlot::Calibration, &mut buf) {
+        // Record saved before a channel was added is shorter, added channels stay uncalibrated.
+        Ok(n) if n <= buf.len() && n % CALIBRATION_RECORD_SIZE == 0 => {
+            CALIBRATIONS.lock(|lock| {
+                let mut calibrations = lock.borrow_mut();
+                for ((calibration, record), channel) in calibrations.iter_mut().zip(buf[..n].chunks(CALIBRATION_RECORD_SIZE)).zip(SensorChannel::all()) {
+                    let gain = f32::from_le_bytes([record[0], record[1], record[2], record[3]]);
+                    let offset = f32::from_le_bytes([record[4], record[5], record[6], record[7]]);
+                    // Corrupted record must not reach conversion. Channel stays uncalibrated.
+                    match check_calibration(gain, offset) {
+                        Ok(()) => *calibration = Calibration { gain: gain, offset: offset, point1: None },
+                        Err(e) => log::warn!("Calibration of {} is invalid, {}", channel.name(), e.as_str()),
+                    }
+                }
+            });
+            log::info!("Calibration loaded.");
+        }
+        Ok(n) => {
+            log::warn!("Calibration has unexpected length: {}", n);
+        }
+        Err(e) => {
+            // Not calibrated yet. Use identity correction.
+            log::warn!("Calibration not loaded: {}", e.as_str());
+        }
+    }
+
+    let mut buf = [0u8; 4];
+    match storage_read(StorageSlot::AdcReference, &mut buf) {
+        Ok(4) => {
+            let vref = f32::from_le_bytes(buf);
+            if vref >= ADC_VREF_MIN && vref <= ADC_VREF_MAX {
+                ADC_VREF.lock(|lock| {
+                    *lock.borrow_mut() = vref
+                });
+            }
+        }
+        _ => {
+            log::warn!("ADC reference voltage not loaded, using nominal.");
+        }
+    }
+}
+
+// ADC reference voltage[V], used to convert RP2040 internal temperature sensor.
+// Thermistor divider is ratiometric to the same supply, so it does not use this.
+pub fn adc_vref() -> f32
+{
+    ADC_VREF.lock(|lock| {
+        *(lock.borrow_mut())
+    })
+}
+
+// Error is out of range only, call save_adc_vref() to persist it.
+pub fn set_adc_vref(vref: f32) -> Result<f32, String>
+{
+    if !(vref >= ADC_VREF_MIN && vref <= ADC_VREF_MAX) {
+        return Err(format!("vref must be in [{:.1}, {:.1}]", ADC_VREF_MIN, ADC_VREF_MAX));
+    }
+    ADC_VREF.lock(|lock| {
+        *lock.borrow_mut() = vref
+    });
+
+    Ok(vref)
+}
+
+pub fn save_adc_vref() -> Result<(), String>
+{
+    storage_write(StorageSlot::AdcReference, &adc_vref().to_le_bytes())
+}
+
+pub fn save_calibration() -> Result<(), String>
+{
+    let mut buf = [0u8; CALIBRATION_RECORD_SIZE * SENSOR_CHANNEL_NUM];
+    CALIBRATIONS.lock(|lock| {
+        for (calibration, record) in lock.borrow().iter().zip(buf.chunks_mut(CALIBRATION_RECORD_SIZE)) {
+            record[0..4].copy_from_slice(&calibration.gain.to_le_bytes());
+            record[4..8].copy_from_slice(&calibration.offset.to_le_bytes());
+        }
+    });
+
+    storage_write(StorageSlot::Calibration, &buf)
+}
@@ -1,32 +1,62 @@
 use core::cell::RefCell;
 use core::ops::{Deref, DerefMut};
 
-use embassy_time::{Duration, Ticker};
+use embassy_time::{Duration, Instant, Ticker};
 use alloc::string::{String};
+use num_traits::float::FloatCore;
 
 use embassy_sync::blocking_mutex::{Mutex, raw::ThreadModeRawMutex};
 
 use crate::thermometer::*;
+use crate::current::*;
 use crate::gpio::*;
 use crate::led::*;
+use crate::diagnostics::*;
+use crate::energy::*;
 use crate::util::*;
+use crate::temperature::*;
+use crate::sensor::*;
 
 
 static ERROR_DETECTOR : Mutex<ThreadModeRawMutex, RefCell<Option<ErrorDetector>>> = Mutex::new(RefCell::new(None));
 static CTRL_SEQ:  Mutex<ThreadModeRawMutex, RefCell<State>> = Mutex::new(RefCell::new(State::Initializing));
+static RAMP_SETPOINT : Mutex<ThreadModeRawMutex, RefCell<RampSetpoint>> = Mutex::new(RefCell::new(RampSetpoint::new()));
+// Number of times each fault is detected since boot, indexed same as FAULT_NAMES.
+static FAULT_COUNTS : Mutex<ThreadModeRawMutex, RefCell<[u32; FAULT_NAMES.len()]>> = Mutex::new(RefCell::new([0; FAULT_NAMES.len()]));
 
 // Control heater 
 const HEATER_CONTROL_TASK_TICK_MS : u32 = 50;
 const HEATER_ON_DETECT_TIME_MS : u32 = 5000;
 const HEATER_OFF_DETECT_TIME_MS : u32 = 1000;
-const HEATER_ON_THRESHOLD_CELCIUS : f32 = 34.0;
-const HEATER_OFF_THRESHOLD_CELCIUS : f32 = 35.0;
+const HEATER_ON_THRESHOLD : Temperature = Temperature::from_centi(3400);
+const HEATER_OFF_THRESHOLD : Temperature = Temperature::from_centi(3500);
+const HEATER_HYSTERESIS : Temperature = Temperature::from_centi(HEATER_OFF_THRESHOLD.centi() - HEATER_ON_THRESHOLD.centi());
+
+// Soft start
+const HEATER_RAMP_RATE_CELCIUS_PER_MIN : f32 = 1.0;
+const HEATER_RAMP_RATE_MAX_CELCIUS_PER_MIN : f32 = 10.0;
+const HEATER_TARGET_MIN : Temperature = Temperature::from_centi(0);
+const HEATER_TARGET_MAX : Temperature = Temperature::from_centi(4000);
 
 // Detect Error
 const ERROR_OVERHEAT_DETECT_TIME_MS : u32 = 2000;
 const ERROR_CTH_DISCONNECT_DETECT_TIME_MS : u32 = 1000;
-const ERROR_OVERHEAT_THRESHOLD_CELCIUS : f32 = 45.0;
-const ERROR_CTH_DISCONNECT_THRESHOLD_CELCIUS : f32 = -10.0;
+const ERROR_OVERHEAT_THRESHOLD : Temperature = Temperature::from_centi(4500);
+const ERROR_CTH_DISCONNECT_THRESHOLD : Temperature = Temperature::from_centi(-1000);
+const ERROR_OPEN_ELEMENT_DETECT_TIME_MS : u32 = 1000;
+const ERROR_RELAY_WELDED_DETECT_TIME_MS : u32 = 1000;
+const ERROR_OVERCURRENT_DETECT_TIME_MS : u32 = 200;
+const ERROR_OPEN_ELEMENT_THRESHOLD_AMPERE : f32 = 0.2;
+const ERROR_RELAY_WELDED_THRESHOLD_AMPERE : f32 = 0.2;
+const ERROR_OVERCURRENT_THRESHOLD_AMPERE : f32 = 8.0;
+// Enclosure temperature is measured by RP2040 internal sensor.
+const ERROR_ENCLOSURE_OVERHEAT_DETECT_TIME_MS : u32 = 5000;
+const ERROR_ENCLOSURE_OVERHEAT_THRESHOLD : Temperature = Temperature::from_centi(6000);
+// Name of each fault, for fault_counts().
+pub const FAULT_NAMES : [&str; 6] = [
+    "heater1_overheat", "heater1_thermistor_disconnect", "heater1_open_element",
+    "heater1_relay_welded", "heater1_overcurrent", "enclosure_overheat",
+];
 
 
 #[derive(Copy, Clone)]
@@ -35,6 +65,8 @@ pub enum State
     Initializing,
     Heating,
     Saturating,
+    // Stopped by operator, heater is off until started again.
+    Stopped,
     Error,
 }
 
@@ -56,10 +88,10 @@ impl HeaterControl
         }
     }
 
-    pub fn control(&mut self, temperature: f32)
+    pub fn control(&mut self, temperature: Temperature, setpoint: Temperature)
     {
-        self.detect_heater_on(temperature);
-        self.detect_heater_off(temperature);
+        self.detect_heater_on(temperature, setpoint);
+        self.detect_heater_off(temperature, setpoint);
     }
 
     pub fn is_on(&self) -> bool 
@@ -67,20 +99,20 @@ impl HeaterControl
         self.heater_is_on
     }
 
-    fn detect_heater_on(&mut self, temperature: f32)
+    fn detect_heater_on(&mut self, temperature: Temperature, setpoint: Temperature)
     {
         if self.heater_is_on == false {
-            if self.heater_on_cnt.count( temperature < HEATER_ON_THRESHOLD_CELCIUS ).is_reach_limit() {
+            if self.heater_on_cnt.count( temperature < setpoint - HEATER_HYSTERESIS ).is_reach_limit() {
                 self.heater_on();
                 self.heater_on_cnt.reset();
             }
         }
     }
 
-    fn detect_heater_off(&mut self, temperature: f32)
+    fn detect_heater_off(&mut self, temperature: Temperature, setpoint: Temperature)
     {
         if self.heater_is_on == true {
-            if self.heater_off_cnt.count( temperature >= HEATER_OFF_THRESHOLD_CELCIUS ).is_reach_limit() {
+            if self.heater_off_cnt.count( temperature >= setpoint ).is_reach_limit() {
                 self.heater_off();
                 self.heater_off_cnt.reset();
             }
@@ -98,18 +130,72 @@ impl HeaterControl
     }
 }
 
+#[derive(Copy, Clone)]
+pub struct RampSetpoint
+{
+    // Kept in f32, step per control tick is smaller than 0.01 celsius.
+    setpoint : Option<f32>,
+    target : Temperature,
+    rate_per_min : f32,
+}
+
+impl RampSetpoint
+{
+    pub const fn new() -> Self
+    {
+        Self {
+            setpoint: None,
+            target: HEATER_OFF_THRESHOLD,
+            rate_per_min: HEATER_RAMP_RATE_CELCIUS_PER_MIN,
+        }
+    }
+
+    // Move setpoint toward target by one control tick.
+    pub fn update(&mut self, temperature: Temperature) -> Temperature
+    {
+        let step = self.rate_per_min * HEATER_CONTROL_TASK_TICK_MS as f32 / 60000.0;
+        let target = self.target.celsius();
+        let setpoint = match self.setpoint {
+            // Start ramp from current temperature.
+            None => temperature.celsius().min(target),
+            // Heating is rate limited, cooling down follows target immediately.
+            Some(setpoint) => (setpoint + step).min(target),
+        };
+
+        self.setpoint = Some(setpoint);
+        Temperature::from_celsius(setpoint)
+    }
+
+    pub fn restart(&mut self)
+    {
+        self.setpoint = None;
+    }
+
+    pub fn setpoint(&self) -> Option<Temperature> { self.setpoint.map(|sp| Temperature::from_celsius(sp)) }
+    pub fn target(&self) -> Temperature { self.target }
+    pub fn rate_per_min(&self) -> f32 { self.rate_per_min }
+}
+
 #[derive(PartialEq, Clone)]
 pub enum ErrorCode
 {
     None,
     Heater1OverHeatError { errcode: u32, message: String },
     Heater1ThermistorDisconnectError { errcode: u32, message: String },
+    Heater1OpenElementError { errcode: u32, message: String },
+    Heater1RelayWeldedError { errcode: u32, message: String },
+    Heater1OverCurrentError { errcode: u32, message: String },
+    EnclosureOverHeatError { errcode: u32, message: String },
 }
 
 struct ErrorDetector
 {
     heater_overheat: Counter,
     heater_thermistor_disconnect: Counter,
+    heater_open_element: Counter,
+    heater_relay_welded: Counter,
+    heater_overcurrent: Counter,
+    enclosure_overheat: Counter,
     detected_error: ErrorCode,
 }
 
@@ -120,28 +206,74 @@ impl ErrorDetector
         Self {
             heater_overheat: Counter::new(ERROR_OVERHEAT_DETECT_TIME_MS / HEATER_CONTROL_TASK_TICK_MS),                    // 50ms * 100 = 5000ms
             heater_thermistor_disconnect: Counter::new(ERROR_CTH_DISCONNECT_DETECT_TIME_MS / HEATER_CONTROL_TASK_TICK_MS), // 50ms * 20  = 1000ms
+            heater_open_element: Counter::new(ERROR_OPEN_ELEMENT_DETECT_TIME_MS / HEATER_CONTROL_TASK_TICK_MS),           // 50ms * 20  = 1000ms
+            heater_relay_welded: Counter::new(ERROR_RELAY_WELDED_DETECT_TIME_MS / HEATER_CONTROL_TASK_TICK_MS),           // 50ms * 20  = 1000ms
+            heater_overcurrent: Counter::new(ERROR_OVERCURRENT_DETECT_TIME_MS / HEATER_CONTROL_TASK_TICK_MS),             // 50ms * 4   = 200ms
+            enclosure_overheat: Counter::new(ERROR_ENCLOSURE_OVERHEAT_DETECT_TIME_MS / HEATER_CONTROL_TASK_TICK_MS),      // 50ms * 100 = 5000ms
             detected_error: ErrorCode::None,
         }
     }
 
-    pub fn heater_overheat(&mut self)
+    pub fn heater_overheat(&mut self, heater1_temp: Temperature)
     {
-        let heater1_temp = heater1_temperature();
-
-        if self.heater_overheat.count( heater1_temp >= ERROR_OVERHEAT_THRESHOLD_CELCIUS ).is_reach_limit() {
+        if self.heater_overheat.count( heater1_temp >= ERROR_OVERHEAT_THRESHOLD ).is_reach_limit() {
             self.detected_error = ErrorCode::Heater1OverHeatError{ errcode: 1, message: String::from("Heater1 overheat error.") };
         }
     }
 
-    pub fn heater_thermistor_disconnect(&mut self)
+    // Sensor fault reads as Temperature::INVALID, so it is detected here too.
+    pub fn heater_thermistor_disconnect(&mut self, heater1_temp: Temperature)
     {
-        let heater1_temp = heater1_temperature();
-
-        if self.heater_thermistor_disconnect.count( heater1_temp < ERROR_CTH_DISCONNECT_THRESHOLD_CELCIUS ).is_reach_limit() {
+        if self.heater_thermistor_disconnect.count( heater1_temp < ERROR_CTH_DISCONNECT_THRESHOLD ).is_reach_limit() {
             self.detected_error = ErrorCode::Heater1ThermistorDisconnectError{ errcode: 2, message: String::from("Heater1 thermistor disconnected error.") };
         }
     }
 
+    // Current detectors are skipped when current sense is not fitted or its output is stuck at a rail.
+    pub fn heater_open_element(&mut self)
+    {
+        // Heater is commanded on, but element draws no current.
+        // Current is signed, so reversed sensor is detected here too.
+        let commanded_on = heater_port_is_on();
+        let sensed = heater1_current_available();
+        let heater1_current = heater1_current();
+
+        if self.heater_open_element.count( sensed && commanded_on && heater1_current < ERROR_OPEN_ELEMENT_THRESHOLD_AMPERE ).is_reach_limit() {
+            self.detected_error = ErrorCode::Heater1OpenElementError{ errcode: 3, message: String::from("Heater1 open element error.") };
+        }
+    }
+
+    pub fn heater_relay_welded(&mut self)
+    {
+        // Heater is commanded off, but element still draws current.
+        let commanded_on = heater_port_is_on();
+        let sensed = heater1_current_available();
+        let heater1_current = heater1_current().abs();
+
+        if self.heater_relay_welded.count( sensed && !commanded_on && heater1_current >= ERROR_RELAY_WELDED_THRESHOLD_AMPERE ).is_reach_limit() {
+            self.detected_error = ErrorCode::Heater1RelayWeldedError{ errcode: 4, message: String::from("Heater1 relay welded error.") };
+        }
+    }
+
+    pub fn heater_overcurrent(&mut self)
+    {
+        let sensed = heater1_current_available();
+        let heater1_current = heater1_current().abs();
+
+        if self.heater_overcurrent.count( sensed && heater1_current >= ERROR_OVERCURRENT_THRESHOLD_AMPERE ).is_reach_limit() {
+            self.detected_error = ErrorCode::Heater1OverCurrentError{ errcode: 5, message: String::from("Heater1 overcurrent error.") };
+        }
+    }
+
+    pub fn enclosure_overheat(&mut self)
+    {
+        let enclosure_temp = cpu_temperature();
+
+        if self.enclosure_overheat.count( enclosure_temp >= ERROR_ENCLOSURE_OVERHEAT_THRESHOLD ).is_reach_limit() {
+            self.detected_error = ErrorCode::EnclosureOverHeatError{ errcode: 6, message: String::from("Enclosure overheat error.") };
+        }
+    }
+
     pub fn errcode(&self) -> ErrorCode
     {
         self.detected_error.clone()
@@ -157,11 +289,26 @@ pub async fn controller_task()
         *(lock.borrow_mut()) = Some(ErrorDetector::new());
     });
     let mut ticker = Ticker::every(Duration::from_millis(HEATER_CONTROL_TASK_TICK_MS as u64));
+    init_loop_timing(TimedLoop::Controller, HEATER_CONTROL_TASK_TICK_MS as u64);
+    let mut was_stopped = false;
+    // Heater1 is read through TemperatureSensor, so thermocouple backend can replace thermistor here.
+    let mut heater1_sensor = ThermistorSensor::new(SensorChannel::Heater1);
 
     loop {
+        record_loop_tick(TimedLoop::Controller);
+
         // input/decision process 
-        control_sequence(&mut heater_controller);
-        detect_error();
+        let heater1_temp = heater1_sensor.read().await.unwrap_or(Temperature::INVALID);
+        control_sequence(&mut heater_controller, heater1_temp);
+        // Heater port is already off here in Stopped and Error.
+        let is_stopped = matches!(current_status(), State::Stopped | State::Error);
+        detect_error(heater1_temp);
+        account_heater1_energy(heater_port_is_on(), Instant::now());
+        // Heater is often powered off after stopped, save counters without waiting periodic save.
+        if is_stopped && !was_stopped {
+            request_energy_save();
+        }
+        was_stopped = is_stopped;
 
         // output process
         set_led_status();
@@ -170,20 +317,23 @@ pub async fn controller_task()
     }
 }
 
-fn control_sequence(mut heater_controller: &mut HeaterControl)
+fn control_sequence(mut heater_controller: &mut HeaterControl, heater1_temp: Temperature)
 {
     CTRL_SEQ.lock( |lock| {
         let mut state = lock.borrow_mut();
         let next_state;
         match *state {
             State::Initializing => {
-                next_state = heater_control(&mut heater_controller);
+                next_state = heater_control(&mut heater_controller, heater1_temp);
             }
             State::Heating => {
-                next_state = heater_control(&mut heater_controller);
+                next_state = heater_control(&mut heater_controller, heater1_temp);
             }
             State::Saturating => {
-                next_state = heater_control(&mut heater_controller);
+                next_state = heater_control(&mut heater_controller, heater1_temp);
+            }
+            State::Stopped => {
+                next_state = control_on_stop();
             }
             State::Error => {
                 next_state = control_on_error();
@@ -194,10 +344,18 @@ fn control_sequence(mut heater_controller: &mut HeaterControl)
     });
 }
 
-fn heater_control(heater_controller: &mut HeaterControl) -> State
+fn heater_control(heater_controller: &mut HeaterControl, heater1_temp: Temperature) -> State
 {
-    let heater1_temp = heater1_temperature();
-    heater_controller.control( heater1_temp );
+    // Sensor fault. Keep heater off and ramp untouched until reading recovers or error is detected.
+    if !heater1_temp.is_valid() {
+        off_heater_port();
+        return State::Saturating;
+    }
+
+    let setpoint = RAMP_SETPOINT.lock(|lock| {
+        lock.borrow_mut().update(heater1_temp)
+    });
+    heater_controller.control( heater1_temp, setpoint );
 
     if heater_controller.is_on() {
         on_heater_port();
@@ -209,32 +367,71 @@ fn heater_control(heater_controller: &mut HeaterControl) -> State
     }
 }
 
+fn control_on_stop() -> State
+{
+    off_heater_port();
+    // Ramp starts from current temperature when started again.
+    RAMP_SETPOINT.lock(|lock| {
+        lock.borrow_mut().restart();
+    });
+
+    State::Stopped
+}
+
 fn control_on_error() -> State
 {
     // heater force off.
     off_heater_port();
+    RAMP_SETPOINT.lock(|lock| {
+        lock.borrow_mut().restart();
+    });
 
     // Fix error state.
     State::Error
 }
 
-fn detect_error()
+fn detect_error(heater1_temp: Temperature)
 {
     ERROR_DETECTOR.lock(|lock| {
         if let Some(ref mut e) = lock.borrow_mut().deref_mut().as_mut() {
-            e.heater_overheat();
-            e.heater_thermistor_disconnect();
+            e.heater_overheat(heater1_temp);
+            e.heater_thermistor_disconnect(heater1_temp);
+            e.heater_open_element();
+            e.heater_relay_welded();
+            e.heater_overcurrent();
+            e.enclosure_overheat();
         }
     });
 
     let errc = errcode();
     if errc != ErrorCode::None {
         CTRL_SEQ.lock( |lock| {
-            *(lock.borrow_mut()) = State::Error;
+            let mut state = lock.borrow_mut();
+            // Error is latched, count it only when detected.
+            if !matches!(*state, State::Error) {
+                count_fault(&errc);
+            }
+            *state = State::Error;
         });
     }
 }
 
+fn count_fault(errc: &ErrorCode)
+{
+    let index = match errc {
+        ErrorCode::None => return,
+        ErrorCode::Heater1OverHeatError {..} => 0,
+        ErrorCode::Heater1ThermistorDisconnectError {..} => 1,
+        ErrorCode::Heater1OpenElementError {..} => 2,
+        ErrorCode::Heater1RelayWeldedError {..} => 3,
+        ErrorCode::Heater1OverCurrentError {..} => 4,
+        ErrorCode::EnclosureOverHeatError {..} => 5,
+    };
+    FAULT_COUNTS.lock(|lock| {
+        lock.borrow_mut()[index] += 1;
+    });
+}
+
 fn set_led_status()
 {
     CTRL_SEQ.lock( |lock| {
@@ -248,6 +445,9 @@ fn set_led_status()
             State::Saturating => {
                 set_led(LedStatus::Saturating);
             }
+            State::Stopped => {
+                set_led(LedStatus::Stop);
+            }
             State::Error => {
                 set_led(LedStatus::Error);
             }
@@ -267,6 +467,14 @@ pub fn errcode() -> ErrorCode
     errcode
 }
 
+// Indexed same as FAULT_NAMES.
+pub fn fault_counts() -> [u32; FAULT_NAMES.len()]
+{
+    FAULT_COUNTS.lock(|lock| {
+        *(lock.borrow_mut())
+    })
+}
+
 pub fn current_status() -> State
 {
     CTRL_SEQ.lock( |lock| {
@@ -274,3 +482,85 @@ pub fn current_status() -> State
     })
 }
 
+pub fn ramp_setpoint() -> RampSetpoint
+{
+    RAMP_SETPOINT.lock(|lock| {
+        *(lock.borrow_mut())
+    })
+}
+
+// Change target temperature and/or heating rate. Ramp continues from current setpoint.
+// rate_per_min is in Celsius per minute.
+pub fn set_ramp(target: Option<Temperature>, rate_per_min: Option<f32>) -> Result<RampSetpoint, String>
+{
+    if let Some(t) = target {
+        // INVALID is below any limit, so non-finite input is rejected here too.
+        if !(t.is_valid() && t >= HEATER_TARGET_MIN && t < HEATER_TARGET_MAX) {
+            return Err(format!("target must be in [{:.1}, {:.1}) celsius", HEATER_TARGET_MIN.celsius(), HEATER_TARGET_MAX.celsius()));
+        }
+    }
+    if let Some(r) = rate_per_min {
+        if !(r.is_finite() && r > 0.0 && r <= HEATER_RAMP_RATE_MAX_CELCIUS_PER_MIN) {
+            return Err(format!("ramp_rate must be in (0.0, {:.1}]", HEATER_RAMP_RATE_MAX_CELCIUS_PER_MIN));
+        }
+    }
+
+    Ok(RAMP_SETPOINT.lock(|lock| {
+        let mut ramp = lock.borrow_mut();
+        if let Some(t) = target {
+            ramp.target = t;
+        }
+        if let Some(r) = rate_per_min {
+            ramp.rate_per_min = r;
+        }
+        *ramp
+    }))
+}
+
+// Start heating stopped by stop_heater().
+pub fn start_heater() -> Result<(), String>
+{
+    CTRL_SEQ.lock( |lock| {
+        let mut state = lock.borrow_mut();
+        match *state {
+            State::Error => Err(String::from("controller is in error state, acknowledge error first")),
+            State::Stopped => {
+                *state = State::Initializing;
+                Ok(())
+            }
+            // Already running.
+            _ => Ok(()),
+        }
+    })
+}
+
+// Stop heating. Error state is kept, it is cleared only by acknowledge_error().
+pub fn stop_heater() -> Result<(), String>
+{
+    CTRL_SEQ.lock( |lock| {
+        let mut state = lock.borrow_mut();
+        if let State::Error = *state {
+            return Ok(());
+        }
+        *state = State::Stopped;
+        Ok(())
+    })
+}
+
+// Clear latched error. Heater stays stopped until started,
+// and error is detected again if cause remains.
+pub fn acknowledge_error() -> Result<(), String>
+{
+    if !matches!(current_status(), State::Error) {
+        return Err(String::from("no error to acknowledge"));
+    }
+
+    ERROR_DETECTOR.lock(|lock| {
+        *(lock.borrow_mut()) = Some(ErrorDetector::new());
+    });
+    CTRL_SEQ.lock( |lock| {
+        *(lock.borrow_mut()) = State::Stopped;
+    });
+
+    Ok(())
+}
@@ -0,0 +1,111 @@
+use core::cell::RefCell;
+use num_traits::float::FloatCore;
+
+use embassy_sync::blocking_mutex::{Mutex, raw::ThreadModeRawMutex};
+
+use crate::adc_dma::{ADC_SATURATION_MARGIN, ADC_MAX_VALUE};
+use crate::sensor::*;
+
+//
+// static const variables
+//
+
+// Current sense amplifier output (ACS712-05B, 185mV/A, output centered at Vref/2)
+const ADC_REFERENCE_MV : f32 = 3300.0;
+const ADC_FULL_SCALE : f32 = 4096.0;
+const CURRENT_SENSE_ZERO_MV : f32 = 1650.0;
+const CURRENT_SENSE_MV_PER_AMPERE : f32 = 185.0;
+
+// ACS712 is fitted on PIN_28. Boards without it are built with --no-default-features,
+// then current based error detection is disabled.
+pub const CURRENT_SENSE_FITTED : bool = cfg!(feature = "current-sense");
+
+// Heater supply voltage, used to calculate heater power.
+const HEATER1_SUPPLY_VOLTAGE : f32 = 12.0;
+
+//
+// static variables
+//
+static HEATER1_CURRENT : Mutex<ThreadModeRawMutex, RefCell<f32>> = Mutex::new(RefCell::new(0.0));
+static HEATER1_CURRENT_FAULT : Mutex<ThreadModeRawMutex, RefCell<Option<SensorFault>>> = Mutex::new(RefCell::new(None));
+
+pub struct CurrentSensor
+{
+    current : f32,
+    exp_mov_ave_alpha : f32,
+}
+
+impl CurrentSensor
+{
+    pub fn new(alpha: f32) -> Self {
+        Self {
+            current: 0.0,
+            exp_mov_ave_alpha: alpha
+        }
+    }
+
+    // Sensor output rests at Vref/2, output stuck at a rail is broken wiring or unfitted floating input.
+    pub fn calc_next(&mut self, adc_value: u16) -> Result<f32, SensorFault>
+    {
+        if adc_value <= ADC_SATURATION_MARGIN {
+            return Err(SensorFault::ShortToGnd);
+        }
+        if adc_value >= ADC_MAX_VALUE - ADC_SATURATION_MARGIN {
+            return Err(SensorFault::ShortToVcc);
+        }
+
+        let current_ampere : f32 = convert_to_ampere(adc_value);
+        self.current = (current_ampere * self.exp_mov_ave_alpha) + ((1.0-self.exp_mov_ave_alpha) * self.current);
+
+        Ok(self.current)
+    }
+}
+
+pub fn convert_to_ampere(adc_value: u16) -> f32
+{
+    let millivolt = adc_value as f32 * ADC_REFERENCE_MV / ADC_FULL_SCALE;
+
+    // Signed, reversed or broken sensor reads negative while heater is on.
+    (millivolt - CURRENT_SENSE_ZERO_MV) / CURRENT_SENSE_MV_PER_AMPERE
+}
+
+pub fn set_heater1_current(current: f32)
+{
+    HEATER1_CURRENT.lock(|lock| {
+        *lock.borrow_mut() = current
+    });
+}
+
+pub fn set_heater1_current_fault(fault: Option<SensorFault>)
+{
+    HEATER1_CURRENT_FAULT.lock(|lock| {
+        *lock.borrow_mut() = fault
+    });
+}
+
+pub fn heater1_current_fault() -> Option<SensorFault>
+{
+    HEATER1_CURRENT_FAULT.lock(|lock| {
+        *(lock.borrow_mut())
+    })
+}
+
+// Current reading can be used for error detection.
+pub fn heater1_current_available() -> bool
+{
+    CURRENT_SENSE_FITTED && heater1_current_fault().is_none()
+}
+
+pub fn heater1_current() -> f32
+{
+    let current = HEATER1_CURRENT.lock(|lock| {
+        *(lock.borrow_mut())
+    });
+    (current * 1000.0 + 0.5).round() / 1000.0
+}
+
+pub fn heater1_power() -> f32
+{
+    let power = heater1_current() * HEATER1_SUPPLY_VOLTAGE;
+    (power * 100.0 + 0.5).round() / 100.0
+}
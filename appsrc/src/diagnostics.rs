@@ -0,0 +1,156 @@
+use core::cell::RefCell;
+
+use embassy_time::{Duration, Instant};
+use embassy_sync::blocking_mutex::{Mutex, raw::ThreadModeRawMutex};
+
+//
+// static const variables
+//
+
+// Loop timing warning is cleared after this time passes without overrun.
+const LOOP_WARNING_CLEAR_TIME_MS : u64 = 1000;
+
+#[derive(Copy, Clone)]
+pub enum TimedLoop
+{
+    Controller,
+    Thermometer,
+}
+
+#[derive(Copy, Clone)]
+pub struct LoopTiming
+{
+    period_us : u64,
+    expected : Option<Instant>,
+    last : Option<Instant>,
+    ticks : u32,
+    min_period_us : u64,
+    max_period_us : u64,
+    sum_period_us : u64,
+    max_latency_us : u64,
+    overruns : u32,
+    last_overrun : Option<Instant>,
+    warning : bool,
+}
+
+//
+// static variables
+//
+static CONTROLLER_TIMING : Mutex<ThreadModeRawMutex, RefCell<LoopTiming>> = Mutex::new(RefCell::new(LoopTiming::new(0)));
+static THERMOMETER_TIMING : Mutex<ThreadModeRawMutex, RefCell<LoopTiming>> = Mutex::new(RefCell::new(LoopTiming::new(0)));
+
+impl LoopTiming
+{
+    pub const fn new(period_ms: u64) -> Self {
+        Self {
+            period_us: period_ms * 1000,
+            expected: None,
+            last: None,
+            ticks: 0,
+            min_period_us: u64::MAX,
+            max_period_us: 0,
+            sum_period_us: 0,
+            max_latency_us: 0,
+            overruns: 0,
+            last_overrun: None,
+            warning: false,
+        }
+    }
+
+    // Call once per loop, right after the ticker wakes the task up.
+    pub fn record(&mut self, now: Instant) -> bool
+    {
+        let period = Duration::from_micros(self.period_us);
+
+        if let Some(last) = self.last {
+            let period_us = (now - last).as_micros();
+            self.ticks += 1;
+            self.sum_period_us += period_us;
+            self.min_period_us = self.min_period_us.min(period_us);
+            self.max_period_us = self.max_period_us.max(period_us);
+        }
+
+        // Ticker deadlines advance by exactly one period, even if a tick is late.
+        let expected = self.expected.unwrap_or(now);
+        let latency_us = if now > expected { (now - expected).as_micros() } else { 0 };
+        self.max_latency_us = self.max_latency_us.max(latency_us);
+
+        // Overrun: the task woke up later than the next tick should have started.
+        let mut warning_raised = false;
+        if latency_us >= self.period_us {
+            self.overruns += 1;
+            self.last_overrun = Some(now);
+            warning_raised = !self.warning;
+            self.warning = true;
+        }
+        else if let Some(last_overrun) = self.last_overrun {
+            if (now - last_overrun) >= Duration::from_millis(LOOP_WARNING_CLEAR_TIME_MS) {
+                self.warning = false;
+            }
+        }
+
+        self.last = Some(now);
+        self.expected = Some(expected + period);
+
+        warning_raised
+    }
+
+    pub fn period_ms(&self) -> u64 { self.period_us / 1000 }
+    pub fn ticks(&self) -> u32 { self.ticks }
+    pub fn min_period_us(&self) -> u64 { if self.ticks > 0 { self.min_period_us } else { 0 } }
+    pub fn max_period_us(&self) -> u64 { self.max_period_us }
+    pub fn avg_period_us(&self) -> u64 { if self.ticks > 0 { self.sum_period_us / self.ticks as u64 } else { 0 } }
+    pub fn max_latency_us(&self) -> u64 { self.max_latency_us }
+    pub fn overruns(&self) -> u32 { self.overruns }
+    pub fn warning(&self) -> bool { self.warning }
+}
+
+fn timing_of(timed_loop: TimedLoop) -> &'static Mutex<ThreadModeRawMutex, RefCell<LoopTiming>>
+{
+    match timed_loop {
+        TimedLoop::Controller => &CONTROLLER_TIMING,
+        TimedLoop::Thermometer => &THERMOMETER_TIMING,
+    }
+}
+
+pub fn init_loop_timing(timed_loop: TimedLoop, period_ms: u64)
+{
+    timing_of(timed_loop).lock(|lock| {
+        *(lock.borrow_mut()) = LoopTiming::new(period_ms);
+    });
+}
+
+pub fn record_loop_tick(timed_loop: TimedLoop)
+{
+    let now = Instant::now();
+    let (warning_raised, timing) = timing_of(timed_loop).lock(|lock| {
+        let mut timing = lock.borrow_mut();
+        (timing.record(now), *timing)
+    });
+
+    if warning_raised {
+        log::warn!("{} loop overrun: max latency {}us, period {}ms",
+            loop_name(timed_loop), timing.max_latency_us(), timing.period_ms()
+        );
+    }
+}
+
+pub fn loop_timing(timed_loop: TimedLoop) -> LoopTiming
+{
+    timing_of(timed_loop).lock(|lock| {
+        *(lock.borrow_mut())
+    })
+}
+
+pub fn loop_timing_warning() -> bool
+{
+    loop_timing(TimedLoop::Controller).warning() || loop_timing(TimedLoop::Thermometer).warning()
+}
+
+pub fn loop_name(timed_loop: TimedLoop) -> &'static str
+{
+    match timed_loop {
+        TimedLoop::Controller => "controller",
+        TimedLoop::Thermometer => "thermometer",
+    }
+}
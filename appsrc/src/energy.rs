@@ -0,0 +1,190 @@
+use core::cell::RefCell;
+
+use alloc::string::String;
+
+use embassy_time::{Duration, Instant, Ticker};
+use embassy_futures::select::select;
+use embassy_sync::blocking_mutex::{Mutex, raw::ThreadModeRawMutex};
+use embassy_sync::signal::Signal;
+
+use crate::storage::*;
+
+//
+// static const variables
+//
+
+// Heater rated power, used to convert on-time into energy.
+pub const HEATER1_WATTAGE : f32 = 60.0;
+// Lifetime counters are written to flash at this interval only if changed, to limit flash wear.
+// Controller also requests save when heater is stopped or fails.
+const ENERGY_SAVE_INTERVAL_MS : u64 = 15 * 60 * 1000;
+const MS_PER_HOUR : f32 = 3600.0 * 1000.0;
+
+#[derive(Copy, Clone)]
+pub struct EnergyCounter
+{
+    heater_was_on : bool,
+    last : Option<Instant>,
+    session_on_ms : u64,
+    session_cycles : u32,
+    lifetime_on_ms : u64,
+    lifetime_cycles : u32,
+    unsaved : bool,
+}
+
+//
+// static variables
+//
+static HEATER1_ENERGY : Mutex<ThreadModeRawMutex, RefCell<EnergyCounter>> = Mutex::new(RefCell::new(EnergyCounter::new()));
+// Flash erase and write take tens of ms, so they are done in energy_task instead of the caller.
+static ENERGY_SAVE_REQUEST : Signal<ThreadModeRawMutex, ()> = Signal::new();
+
+impl EnergyCounter
+{
+    pub const fn new() -> Self
+    {
+        Self {
+            heater_was_on: false,
+            last: None,
+            session_on_ms: 0,
+            session_cycles: 0,
+            lifetime_on_ms: 0,
+            lifetime_cycles: 0,
+            unsaved: false,
+        }
+    }
+
+    // Heater output was held since last call, so measured time is added to on-time if it was on.
+    // Late control tick is accounted by its real length.
+    pub fn count(&mut self, heater_is_on: bool, now: Instant)
+    {
+        let elapsed_ms = self.last.map_or(0, |last| (now - last).as_millis());
+        self.last = Some(now);
+        if self.heater_was_on {
+            self.session_on_ms += elapsed_ms;
+            self.lifetime_on_ms += elapsed_ms;
+            self.unsaved = true;
+        }
+        // Count off -> on transition as one cycle.
+        if heater_is_on && !self.heater_was_on {
+            self.session_cycles += 1;
+            self.lifetime_cycles += 1;
+        }
+        self.heater_was_on = heater_is_on;
+    }
+
+    pub fn reset_session(&mut self)
+    {
+        self.session_on_ms = 0;
+        self.session_cycles = 0;
+    }
+
+    pub fn session_on_time_s(&self) -> u64 { self.session_on_ms / 1000 }
+    pub fn session_cycles(&self) -> u32 { self.session_cycles }
+    pub fn session_kwh(&self) -> f32 { on_time_to_kwh(self.session_on_ms) }
+    pub fn lifetime_on_time_s(&self) -> u64 { self.lifetime_on_ms / 1000 }
+    pub fn lifetime_cycles(&self) -> u32 { self.lifetime_cycles }
+    pub fn lifetime_kwh(&self) -> f32 { on_time_to_kwh(self.lifetime_on_ms) }
+
+    fn to_bytes(&self) -> [u8; 12]
+    {
+        let mut buf = [0u8; 12];
+        buf[0..8].copy_from_slice(&self.lifetime_on_ms.to_le_bytes());
+        buf[8..12].copy_from_slice(&self.lifetime_cycles.to_le_bytes());
+        buf
+    }
+
+    fn load_bytes(&mut self, buf: &[u8; 12])
+    {
+        self.lifetime_on_ms = u64::from_le_bytes([buf[0], buf[1], buf[2], buf[3], buf[4], buf[5], buf[6], buf[7]]);
+        self.lifetime_cycles = u32::from_le_bytes([buf[8], buf[9], buf[10], buf[11]]);
+    }
+}
+
+fn on_time_to_kwh(on_ms: u64) -> f32
+{
+    (on_ms as f32 / MS_PER_HOUR) * HEATER1_WATTAGE / 1000.0
+}
+
+#[embassy_executor::task]
+pub async fn energy_task()
+{
+    load_energy();
+
+    let mut ticker = Ticker::every(Duration::from_millis(ENERGY_SAVE_INTERVAL_MS));
+    loop {
+        // Periodic save, or save requested by request_energy_save().
+        select(ticker.next(), ENERGY_SAVE_REQUEST.wait()).await;
+        if let Err(e) = save_energy() {
+            log::warn!("Energy counters save failed: {}", e.as_str());
+        }
+    }
+}
+
+fn load_energy()
+{
+    let mut buf = [0u8; 12];
+    match storage_read(StorageSlot::Energy, &mut buf) {
+        Ok(12) => {
+            HEATER1_ENERGY.lock(|lock| {
+                lock.borrow_mut().load_bytes(&buf);
+            });
+            log::info!("Energy counters loaded.");
+        }
+        Ok(n) => {
+            log::warn!("Energy counters have unexpected length: {}", n);
+        }
+        Err(e) => {
+            // First boot, or storage broken. Start lifetime counters from zero.
+            log::warn!("Energy counters not loaded: {}", e.as_str());
+        }
+    }
+}
+
+// Error is flash write failure, counters stay unsaved and are retried by next save.
+pub fn save_energy() -> Result<(), String>
+{
+    let (unsaved, bytes) = HEATER1_ENERGY.lock(|lock| {
+        let counter = lock.borrow_mut();
+        (counter.unsaved, counter.to_bytes())
+    });
+    if !unsaved {
+        return Ok(());
+    }
+
+    storage_write(StorageSlot::Energy, &bytes)?;
+    HEATER1_ENERGY.lock(|lock| {
+        lock.borrow_mut().unsaved = false;
+    });
+    log::info!("Energy counters saved.");
+
+    Ok(())
+}
+
+// Save counters in energy_task without blocking the caller.
+pub fn request_energy_save()
+{
+    ENERGY_SAVE_REQUEST.signal(());
+}
+
+// Called every control tick with commanded heater output.
+pub fn account_heater1_energy(heater_is_on: bool, now: Instant)
+{
+    HEATER1_ENERGY.lock(|lock| {
+        lock.borrow_mut().count(heater_is_on, now);
+    });
+}
+
+pub fn reset_heater1_energy_session()
+{
+    HEATER1_ENERGY.lock(|lock| {
+        lock.borrow_mut().reset_session();
+    });
+}
+
+pub fn heater1_energy() -> EnergyCounter
+{
+    HEATER1_ENERGY.lock(|lock| {
+        *(lock.borrow_mut())
+    })
+}
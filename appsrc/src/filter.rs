@@ -0,0 +1,187 @@
+//
+// static const variables
+//
+// About 200 samples of each channel arrive per thermometer tick, so maximum uses all of them.
+pub const OVERSAMPLING_MAX : usize = 256;
+pub const MEDIAN_WINDOW_MAX : usize = 9;
+pub const MOVING_AVERAGE_WINDOW_MAX : usize = 32;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Smoothing
+{
+    None,
+    Ema { alpha: f32 },
+    MovingAverage { window: usize },
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub struct KalmanConfig
+{
+    // Variance of true temperature change per sample.
+    pub process_noise : f32,
+    // Variance of measurement noise.
+    pub measurement_noise : f32,
+}
+
+// Filter pipeline: oversampling -> median -> smoothing -> kalman
+// Oversampling is done before filter chain, by averaging DMA samples of each tick.
+#[derive(Copy, Clone, PartialEq)]
+pub struct FilterConfig
+{
+    // Number of latest DMA samples averaged per tick. All samples of the tick are used if fewer arrived.
+    pub oversampling : usize,
+    // Median of last K samples, 1 disables spike rejection.
+    pub median_window : usize,
+    pub smoothing : Smoothing,
+    pub kalman : Option<KalmanConfig>,
+}
+
+impl FilterConfig
+{
+    // Same as the fixed EMA used before filter chain was introduced.
+    pub const fn new() -> Self
+    {
+        Self {
+            oversampling: OVERSAMPLING_MAX,
+            median_window: 1,
+            smoothing: Smoothing::Ema { alpha: 0.22 },
+            kalman: None,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), &'static str>
+    {
+        if self.oversampling < 1 || self.oversampling > OVERSAMPLING_MAX {
+            return Err("oversampling is out of range");
+        }
+        if self.median_window < 1 || self.median_window > MEDIAN_WINDOW_MAX || self.median_window % 2 == 0 {
+            return Err("median must be odd number and in range");
+        }
+        match self.smoothing {
+            Smoothing::Ema { alpha } if !(alpha > 0.0 && alpha <= 1.0) => return Err("alpha is out of range"),
+            Smoothing::MovingAverage { window } if window < 1 || window > MOVING_AVERAGE_WINDOW_MAX => return Err("window is out of range"),
+            _ => {}
+        }
+        if let Some(kalman) = self.kalman {
+            if !(kalman.process_noise > 0.0 && kalman.measurement_noise > 0.0) {
+                return Err("kalman noise must be positive");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub struct FilterChain
+{
+    config : FilterConfig,
+    median_buf : [f32; MEDIAN_WINDOW_MAX],
+    median_len : usize,
+    median_pos : usize,
+    average_buf : [f32; MOVING_AVERAGE_WINDOW_MAX],
+    average_len : usize,
+    average_pos : usize,
+    smoothed : Option<f32>,
+    kalman_estimate : Option<(f32, f32)>,   // (estimate, error variance)
+}
+
+impl FilterChain
+{
+    pub fn new(config: FilterConfig) -> Self
+    {
+        Self {
+            config: config,
+            median_buf: [0.0; MEDIAN_WINDOW_MAX],
+            median_len: 0,
+            median_pos: 0,
+            average_buf: [0.0; MOVING_AVERAGE_WINDOW_MAX],
+            average_len: 0,
+            average_pos: 0,
+            smoothed: None,
+            kalman_estimate: None,
+        }
+    }
+
+    pub fn config(&self) -> FilterConfig
+    {
+        self.config
+    }
+
+    // Changing config restarts filter from next sample.
+    pub fn set_config(&mut self, config: FilterConfig)
+    {
+        if self.config != config {
+            *self = Self::new(config);
+        }
+    }
+
+    pub fn apply(&mut self, sample: f32) -> f32
+    {
+        let median = self.median(sample);
+        let smoothed = self.smooth(median);
+        self.kalman(smoothed)
+    }
+
+    fn median(&mut self, sample: f32) -> f32
+    {
+        let window = self.config.median_window;
+        if window <= 1 {
+            return sample;
+        }
+
+        self.median_buf[self.median_pos] = sample;
+        self.median_pos = (self.median_pos + 1) % window;
+        self.median_len = (self.median_len + 1).min(window);
+
+        let mut sorted = [0.0f32; MEDIAN_WINDOW_MAX];
+        let sorted = &mut sorted[..self.median_len];
+        sorted.copy_from_slice(&self.median_buf[..self.median_len]);
+        sorted.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap_or(core::cmp::Ordering::Equal));
+
+        sorted[self.median_len / 2]
+    }
+
+    fn smooth(&mut self, sample: f32) -> f32
+    {
+        match self.config.smoothing {
+            Smoothing::None => sample,
+            Smoothing::Ema { alpha } => {
+                // First sample initializes EMA, so output does not ramp up from zero.
+                let smoothed = match self.smoothed {
+                    Some(prev) => (sample * alpha) + ((1.0 - alpha) * prev),
+                    None => sample,
+                };
+                self.smoothed = Some(smoothed);
+                smoothed
+            }
+            Smoothing::MovingAverage { window } => {
+                self.average_buf[self.average_pos] = sample;
+                self.average_pos = (self.average_pos + 1) % window;
+                self.average_len = (self.average_len + 1).min(window);
+
+                self.average_buf[..self.average_len].iter().sum::<f32>() / self.average_len as f32
+            }
+        }
+    }
+
+    fn kalman(&mut self, sample: f32) -> f32
+    {
+        let kalman = match self.config.kalman {
+            Some(k) => k,
+            None => return sample,
+        };
+
+        let (estimate, variance) = match self.kalman_estimate {
+            Some((x, p)) => {
+                // predict (temperature assumed constant), then update
+                let p = p + kalman.process_noise;
+                let gain = p / (p + kalman.measurement_noise);
+                (x + gain * (sample - x), (1.0 - gain) * p)
+            }
+            None => (sample, kalman.measurement_noise),
+        };
+        self.kalman_estimate = Some((estimate, variance));
+
+        estimate
+    }
+}
@@ -1,5 +1,5 @@
 use core::cell::RefCell;
-use core::ops::{DerefMut};
+use core::ops::{Deref, DerefMut};
 
 use embassy_rp::gpio;
 use gpio::{Output};
@@ -36,3 +36,12 @@ pub fn off_heater_port()
     });
 }
 
+pub fn heater_port_is_on() -> bool
+{
+    HEATER_PORT.lock(|lock| {
+        match lock.borrow().deref() {
+            Some(heater_port) => heater_port.is_set_high(),
+            None => false,
+        }
+    })
+}
@@ -0,0 +1,255 @@
+use core::cell::RefCell;
+
+use alloc::vec::Vec;
+use embassy_time::{Duration, Instant, Ticker};
+use embassy_sync::blocking_mutex::{Mutex, raw::ThreadModeRawMutex};
+
+use crate::thermometer::*;
+use crate::controller::*;
+use crate::temperature::*;
+
+//
+// static const variables
+//
+
+// Fine tier: 1 second samples for 10 minutes.
+pub const HISTORY_FINE_INTERVAL_S : u32 = 1;
+const HISTORY_FINE_LEN : usize = 600;
+// Coarse tier: 1 minute min/max/avg buckets for 24 hours.
+pub const HISTORY_COARSE_INTERVAL_S : u32 = 60;
+const HISTORY_COARSE_LEN : usize = 1440;
+// Heap is small, so one response returns at most this number of records.
+pub const HISTORY_PAGE_MAX : usize = 60;
+// Temperatures are stored in centi-degrees to save RAM. Setpoint is none while heater is stopped.
+const HISTORY_NO_VALUE : i16 = i16::MIN;
+
+#[derive(Copy, Clone)]
+pub struct HistorySample
+{
+    heater : i16,
+    cpu : i16,
+    setpoint : i16,
+}
+
+#[derive(Copy, Clone)]
+pub struct HistoryBucket
+{
+    heater_avg : i16,
+    heater_min : i16,
+    heater_max : i16,
+    cpu_avg : i16,
+    setpoint_avg : i16,
+}
+
+// Ring of records taken at fixed interval. Time of each record is derived from time of latest record.
+struct HistoryRing<T: Copy, const N: usize>
+{
+    records : [T; N],
+    len : usize,
+    next : usize,
+    interval_s : u32,
+    last_time_s : u32,
+}
+
+// Accumulates fine samples into one coarse bucket.
+struct BucketAccumulator
+{
+    count : u32,
+    heater_sum : i32,
+    heater_min : i16,
+    heater_max : i16,
+    cpu_sum : i32,
+    setpoint_sum : i32,
+    setpoint_count : u32,
+}
+
+struct History
+{
+    fine : HistoryRing<HistorySample, HISTORY_FINE_LEN>,
+    coarse : HistoryRing<HistoryBucket, HISTORY_COARSE_LEN>,
+    accumulator : BucketAccumulator,
+}
+
+//
+// static variables
+//
+static HISTORY : Mutex<ThreadModeRawMutex, RefCell<History>> = Mutex::new(RefCell::new(History::new()));
+
+impl HistorySample
+{
+    pub fn heater(&self) -> Temperature { Temperature::from_centi(self.heater as i32) }
+    pub fn cpu(&self) -> Temperature { Temperature::from_centi(self.cpu as i32) }
+    pub fn setpoint(&self) -> Option<Temperature> { from_centi(self.setpoint) }
+}
+
+impl HistoryBucket
+{
+    pub fn heater_avg(&self) -> Temperature { Temperature::from_centi(self.heater_avg as i32) }
+    pub fn heater_min(&self) -> Temperature { Temperature::from_centi(self.heater_min as i32) }
+    pub fn heater_max(&self) -> Temperature { Temperature::from_centi(self.heater_max as i32) }
+    pub fn cpu_avg(&self) -> Temperature { Temperature::from_centi(self.cpu_avg as i32) }
+    pub fn setpoint_avg(&self) -> Option<Temperature> { from_centi(self.setpoint_avg) }
+}
+
+impl<T: Copy, const N: usize> HistoryRing<T, N>
+{
+    const fn new(init: T, interval_s: u32) -> Self
+    {
+        Self {
+            records: [init; N],
+            len: 0,
+            next: 0,
+            interval_s: interval_s,
+            last_time_s: 0,
+        }
+    }
+
+    fn push(&mut self, record: T, time_s: u32)
+    {
+        self.records[self.next] = record;
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+        self.last_time_s = time_s;
+    }
+
+    fn oldest_time_s(&self) -> u32
+    {
+        self.last_time_s.saturating_sub((self.len.saturating_sub(1)) as u32 * self.interval_s)
+    }
+
+    // Records at or after from_s, oldest first, at most max records.
+    fn page(&self, from_s: u32, max: usize) -> Vec<(u32, T)>
+    {
+        if self.len == 0 {
+            return Vec::new();
+        }
+        let oldest = self.oldest_time_s();
+        let skip = if from_s > oldest { ((from_s - oldest + self.interval_s - 1) / self.interval_s) as usize } else { 0 };
+        let first = (self.next + N - self.len) % N;
+
+        (skip..self.len).take(max).map(|k| {
+            (oldest + k as u32 * self.interval_s, self.records[(first + k) % N])
+        }).collect()
+    }
+}
+
+impl BucketAccumulator
+{
+    const fn new() -> Self
+    {
+        Self {
+            count: 0,
+            heater_sum: 0,
+            heater_min: i16::MAX,
+            heater_max: i16::MIN,
+            cpu_sum: 0,
+            setpoint_sum: 0,
+            setpoint_count: 0,
+        }
+    }
+
+    fn add(&mut self, sample: &HistorySample)
+    {
+        self.count += 1;
+        self.heater_sum += sample.heater as i32;
+        self.heater_min = self.heater_min.min(sample.heater);
+        self.heater_max = self.heater_max.max(sample.heater);
+        self.cpu_sum += sample.cpu as i32;
+        if sample.setpoint != HISTORY_NO_VALUE {
+            self.setpoint_sum += sample.setpoint as i32;
+            self.setpoint_count += 1;
+        }
+    }
+
+    fn bucket(&self) -> HistoryBucket
+    {
+        let average = |sum: i32, count: u32| (sum / count.max(1) as i32) as i16;
+
+        HistoryBucket {
+            heater_avg: average(self.heater_sum, self.count),
+            heater_min: self.heater_min,
+            heater_max: self.heater_max,
+            cpu_avg: average(self.cpu_sum, self.count),
+            setpoint_avg: if self.setpoint_count == 0 { HISTORY_NO_VALUE } else { average(self.setpoint_sum, self.setpoint_count) },
+        }
+    }
+}
+
+impl History
+{
+    const fn new() -> Self
+    {
+        let empty_sample = HistorySample { heater: 0, cpu: 0, setpoint: HISTORY_NO_VALUE };
+        let empty_bucket = HistoryBucket { heater_avg: 0, heater_min: 0, heater_max: 0, cpu_avg: 0, setpoint_avg: HISTORY_NO_VALUE };
+
+        Self {
+            fine: HistoryRing::new(empty_sample, HISTORY_FINE_INTERVAL_S),
+            coarse: HistoryRing::new(empty_bucket, HISTORY_COARSE_INTERVAL_S),
+            accumulator: BucketAccumulator::new(),
+        }
+    }
+
+    fn record(&mut self, sample: HistorySample, time_s: u32)
+    {
+        self.fine.push(sample, time_s);
+        self.accumulator.add(&sample);
+
+        // Bucket time is time of last sample in the bucket.
+        if self.accumulator.count >= HISTORY_COARSE_INTERVAL_S / HISTORY_FINE_INTERVAL_S {
+            self.coarse.push(self.accumulator.bucket(), time_s);
+            self.accumulator = BucketAccumulator::new();
+        }
+    }
+}
+
+#[embassy_executor::task]
+pub async fn history_task()
+{
+    let mut ticker = Ticker::every(Duration::from_secs(HISTORY_FINE_INTERVAL_S as u64));
+
+    loop {
+        ticker.next().await;
+
+        let sample = HistorySample {
+            heater: to_centi(Some(heater1_temperature())),
+            cpu: to_centi(Some(cpu_temperature())),
+            setpoint: to_centi(ramp_setpoint().setpoint()),
+        };
+        let time_s = Instant::now().as_secs() as u32;
+        HISTORY.lock(|lock| {
+            lock.borrow_mut().record(sample, time_s)
+        });
+    }
+}
+
+// 1 second samples at or after from_s [seconds since boot].
+pub fn history_fine(from_s: u32) -> Vec<(u32, HistorySample)>
+{
+    HISTORY.lock(|lock| {
+        lock.borrow().fine.page(from_s, HISTORY_PAGE_MAX)
+    })
+}
+
+// 1 minute buckets at or after from_s [seconds since boot].
+pub fn history_coarse(from_s: u32) -> Vec<(u32, HistoryBucket)>
+{
+    HISTORY.lock(|lock| {
+        lock.borrow().coarse.page(from_s, HISTORY_PAGE_MAX)
+    })
+}
+
+fn to_centi(temperature: Option<Temperature>) -> i16
+{
+    match temperature {
+        Some(t) => t.centi().max(-(i16::MAX as i32)).min(i16::MAX as i32) as i16,
+        None => HISTORY_NO_VALUE,
+    }
+}
+
+fn from_centi(value: i16) -> Option<Temperature>
+{
+    if value == HISTORY_NO_VALUE {
+        return None;
+    }
+    Some(Temperature::from_centi(value as i32))
+}
@@ -3,6 +3,9 @@ use core::cell::RefCell;
 use embassy_time::{Duration, Ticker};
 use embassy_sync::blocking_mutex::{Mutex, raw::ThreadModeRawMutex};
 
+#[cfg(feature = "wifi-rssi")]
+use crate::wifi::*;
+
 // Onboard LED Status
 #[derive(Copy, Clone)]
 pub enum LedStatus
@@ -57,6 +60,10 @@ pub async fn led_task(mut control: cyw43::Control<'static>) -> !
         }
 
         control.gpio_set(0, led).await;
+        // led_task owns cyw43 Control, so it reads RSSI when /metrics asks for it.
+        #[cfg(feature = "wifi-rssi")]
+        update_wifi_rssi(&mut control).await;
+
         ticker.next().await;
         ticks -= 1;
     }
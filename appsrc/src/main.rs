@@ -14,25 +14,54 @@ use embassy_net::tcp::TcpSocket;
 use embassy_net::{Config, Stack, StackResources};
 use embassy_rp::gpio::{Level, Output};
 use embassy_rp::bind_interrupts;
-use embassy_rp::peripherals::{DMA_CH0, PIN_23, PIN_25, PIO0, USB};
+use embassy_rp::peripherals::{DMA_CH0, I2C0, PIN_23, PIN_25, PIO0, USB};
 use embassy_rp::usb::{Driver, InterruptHandler};
 use embassy_rp::adc::Adc;
+use embassy_rp::i2c::I2c;
+use embassy_rp::flash::Flash;
 use embassy_rp::pio::Pio;
 use embedded_alloc::Heap;
 use static_cell::StaticCell;
 use {defmt_rtt as _, panic_probe as _};
 
 mod rest;
+mod rest_schema;
+mod websocket;
+#[cfg(feature = "wifi-rssi")]
+mod wifi;
 mod thermometer;
+mod current;
+mod ambient;
+mod sht3x;
 mod controller;
 mod led;
 mod gpio;
 mod util;
-use crate::rest::Rest;
+mod diagnostics;
+mod storage;
+mod energy;
+mod calibration;
+mod ntc;
+mod filter;
+mod adc_dma;
+mod history;
+mod profile;
+mod temperature;
+mod sensor;
+mod max31855;
+mod stats;
+use crate::rest::{Rest, REST_WORKER_NUM, REST_SOCKET_BUFFER_SIZE};
 use crate::thermometer::*;
+use crate::ambient::*;
 use crate::controller::*;
 use crate::led::*;
 use crate::gpio::*;
+use crate::storage::*;
+use crate::energy::*;
+use crate::calibration::*;
+use crate::history::*;
+use crate::profile::*;
+use crate::temperature::*;
 
 macro_rules! singleton {
     ($val:expr) => {{
@@ -45,6 +74,7 @@ macro_rules! singleton {
 bind_interrupts!(struct Irqs {
     USBCTRL_IRQ => InterruptHandler<USB>;
     ADC_IRQ_FIFO => embassy_rp::adc::InterruptHandler;
+    I2C0_IRQ => embassy_rp::i2c::InterruptHandler<I2C0>;
 });
 
 //
@@ -82,6 +112,31 @@ async fn logger_task(driver: Driver<'static, USB>) {
     let _ = LOGGER.run(&mut ::embassy_usb_logger::LoggerState::new(), driver).await;
 }
 
+// pool_size must be same as REST_WORKER_NUM, task attribute does not accept const.
+const _: () = assert!(REST_WORKER_NUM == 3);
+#[embassy_executor::task(pool_size = 3)]
+async fn rest_task(stack: &'static Stack<cyw43::NetDriver<'static>>, id: usize) -> ! {
+    // Each worker has own socket buffers, so workers can serve connections concurrently.
+    let mut rx_buffer = [0; REST_SOCKET_BUFFER_SIZE];
+    let mut tx_buffer = [0; REST_SOCKET_BUFFER_SIZE];
+
+    loop {
+        let socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+        let mut server = Rest::new(socket);
+
+        // While this worker is serving, other workers keep listening on port 80,
+        // so new connection is not refused.
+        if let Err(s) = server.accept().await {
+            log::warn!("REST worker[{}]: {}", id, s.as_str());
+            continue;
+        }
+        if let Err(s) = server.do_rest_service().await {
+            log::warn!("REST worker[{}]: {}", id, s.as_str());
+        }
+        server.close().await;
+    }
+}
+
 #[embassy_executor::main]
 async fn main(spawner: Spawner)
 {
@@ -96,13 +151,25 @@ async fn main(spawner: Spawner)
     //let usb_driver = Driver::new(p.USB, Irqs);
     //spawner.spawn(logger_task(usb_driver)).unwrap();
 
+    // Persistent storage, and load lifetime counters before controller starts.
+    set_storage_flash(Flash::<_, FLASH_SIZE>::new(p.FLASH));
+    load_calibration();
+    load_profiles();
+    load_default_unit();
+    spawner.spawn(energy_task()).unwrap();
+
     // Set heater gpio
     let heater_port = Output::new(p.PIN_6, Level::Low);
     set_using_gpio_ports(heater_port);
-    // Start thermomater(Heater, CPU)
+    // Start thermomater(Heater, CPU) and heater current sensing, ADC is sampled by DMA
     let adc = Adc::new(p.ADC, Irqs, embassy_rp::adc::Config::default());
-    let adcio = ADCIo::new(adc, p.PIN_26, p.PIN_27);
+    let adcio = ADCIo::new(adc, p.DMA_CH1, p.PIN_26, p.PIN_27, p.PIN_28);
     spawner.spawn(thermometer_task(adcio)).unwrap();
+    // Start ambient temperature/humidity sensor (SHT3x, SDA=GP4, SCL=GP5)
+    let i2c = I2c::new_async(p.I2C0, p.PIN_5, p.PIN_4, Irqs, embassy_rp::i2c::Config::default());
+    spawner.spawn(ambient_task(i2c)).unwrap();
+    // Record temperature history in RAM
+    spawner.spawn(history_task()).unwrap();
 
     log::info!("Hello World!");
 
@@ -145,7 +212,8 @@ async fn main(spawner: Spawner)
     let stack = &*singleton!(Stack::new(
         net_device,
         config,
-        singleton!(StackResources::<2>::new()),
+        // One socket for each REST worker, plus one for DHCP.
+        singleton!(StackResources::<{ REST_WORKER_NUM + 1 }>::new()),
         seed
     ));
 
@@ -166,21 +234,8 @@ async fn main(spawner: Spawner)
     // Start LED task
     spawner.spawn(led_task(control)).unwrap();
 
-    // And now we can use it!
-    let mut rx_buffer = [0; 4096];
-    let mut tx_buffer = [0; 4096];
-
-    loop {
-        let socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
-        let mut server = Rest::new(socket);
-
-        if let Err(s) = server.accept().await {
-            log::warn!("{}", s.as_str());
-            continue;
-        }
-        if let Err(s) = server.do_rest_service().await {
-            log::warn!("{}", s.as_str());
-        }
-        server.close().await;
+    // Start REST workers
+    for id in 0..REST_WORKER_NUM {
+        spawner.spawn(rest_task(stack, id)).unwrap();
     }
 }
@@ -0,0 +1,254 @@
+// MAX31855 / MAX6675 thermocouple drivers. These are library only, main.rs does not instantiate them.
+// Heater1 is read through TemperatureSensor in controller_task, so a thermocouple board replaces
+// ThermistorSensor there with Max31855 on its SPI device.
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::sensor::*;
+use crate::temperature::*;
+
+//
+// static const variables
+//
+
+// MAX31855 32bit frame
+//   D31-D18 : thermocouple temperature, signed 14bit, 0.25 celsius/LSB
+//   D17     : reserved, always 0
+//   D16     : fault
+//   D15-D4  : cold junction temperature, signed 12bit, 0.0625 celsius/LSB
+//   D3      : reserved, always 0
+//   D2      : short to VCC, D1 : short to GND, D0 : open circuit
+const MAX31855_RESERVED_BITS : u32 = (1 << 17) | (1 << 3);
+const MAX31855_FAULT_BIT : u32 = 1 << 16;
+const MAX31855_SCV_BIT : u32 = 1 << 2;
+const MAX31855_SCG_BIT : u32 = 1 << 1;
+const MAX31855_OC_BIT : u32 = 1 << 0;
+
+// MAX6675 16bit frame
+//   D15    : dummy sign bit, always 0
+//   D14-D3 : thermocouple temperature, unsigned 12bit, 0.25 celsius/LSB
+//   D2     : thermocouple open
+//   D1     : device ID, always 0
+const MAX6675_RESERVED_BITS : u16 = (1 << 15) | (1 << 1);
+const MAX6675_OPEN_BIT : u16 = 1 << 2;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ThermocoupleReading
+{
+    pub thermocouple : Temperature,
+    // Chip internal temperature. None for MAX6675.
+    pub cold_junction : Option<Temperature>,
+}
+
+// Decode MAX31855 frame, bytes in order received from SPI (MSB first).
+pub fn decode_max31855(frame: [u8; 4]) -> Result<ThermocoupleReading, SensorFault>
+{
+    let word = u32::from_be_bytes(frame);
+
+    // Reserved bit set means MISO is stuck high or frame is corrupted. All zero frame is valid 0 celsius reading.
+    if word & MAX31855_RESERVED_BITS != 0 {
+        return Err(SensorFault::Bus);
+    }
+    if word & MAX31855_FAULT_BIT != 0 {
+        return Err(
+            if word & MAX31855_OC_BIT != 0 { SensorFault::Open }
+            else if word & MAX31855_SCG_BIT != 0 { SensorFault::ShortToGnd }
+            else if word & MAX31855_SCV_BIT != 0 { SensorFault::ShortToVcc }
+            else { SensorFault::Bus }
+        );
+    }
+
+    // Arithmetic shift keeps sign of 14bit/12bit fields.
+    let thermocouple = (word as i32) >> 18;
+    let cold_junction = ((word << 16) as i32) >> 20;
+
+    Ok(ThermocoupleReading {
+        thermocouple: Temperature::from_centi(thermocouple * 25),
+        cold_junction: Some(Temperature::from_centi(cold_junction * 625 / 100)),
+    })
+}
+
+// Decode MAX6675 frame, bytes in order received from SPI (MSB first).
+pub fn decode_max6675(frame: [u8; 2]) -> Result<ThermocoupleReading, SensorFault>
+{
+    let word = u16::from_be_bytes(frame);
+
+    // Same as MAX31855, all zero frame is valid 0 celsius reading.
+    if word & MAX6675_RESERVED_BITS != 0 {
+        return Err(SensorFault::Bus);
+    }
+    if word & MAX6675_OPEN_BIT != 0 {
+        return Err(SensorFault::Open);
+    }
+
+    Ok(ThermocoupleReading {
+        thermocouple: Temperature::from_centi(((word >> 3) as i32) * 25),
+        cold_junction: None,
+    })
+}
+
+// K-type thermocouple converter with cold junction compensation, read only SPI.
+pub struct Max31855<S: SpiDevice>
+{
+    spi : S,
+    identity : &'static str,
+    last_reading : Option<ThermocoupleReading>,
+}
+
+impl<S: SpiDevice> Max31855<S>
+{
+    pub fn new(spi: S, identity: &'static str) -> Self
+    {
+        Self { spi: spi, identity: identity, last_reading: None }
+    }
+
+    pub async fn read_frame(&mut self) -> Result<ThermocoupleReading, SensorFault>
+    {
+        let mut frame = [0u8; 4];
+        self.spi.read(&mut frame).await.map_err(|_| SensorFault::Bus)?;
+
+        let reading = decode_max31855(frame);
+        self.last_reading = reading.ok();
+        reading
+    }
+
+    // Cold junction temperature of last successful read.
+    pub fn cold_junction(&self) -> Option<Temperature>
+    {
+        self.last_reading.and_then(|r| r.cold_junction)
+    }
+}
+
+impl<S: SpiDevice> TemperatureSensor for Max31855<S>
+{
+    fn identity(&self) -> &'static str
+    {
+        self.identity
+    }
+
+    async fn read(&mut self) -> Result<Temperature, SensorFault>
+    {
+        self.read_frame().await.map(|r| r.thermocouple)
+    }
+}
+
+// Older K-type thermocouple converter, 0..1023.75 celsius, detects open thermocouple only.
+pub struct Max6675<S: SpiDevice>
+{
+    spi : S,
+    identity : &'static str,
+}
+
+impl<S: SpiDevice> Max6675<S>
+{
+    pub fn new(spi: S, identity: &'static str) -> Self
+    {
+        Self { spi: spi, identity: identity }
+    }
+}
+
+impl<S: SpiDevice> TemperatureSensor for Max6675<S>
+{
+    fn identity(&self) -> &'static str
+    {
+        self.identity
+    }
+
+    // MAX6675 needs at least 220ms between reads for conversion.
+    async fn read(&mut self) -> Result<Temperature, SensorFault>
+    {
+        let mut frame = [0u8; 2];
+        self.spi.read(&mut frame).await.map_err(|_| SensorFault::Bus)?;
+
+        decode_max6675(frame).map(|r| r.thermocouple)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn max31855_frame(thermocouple_quarter: i32, cold_junction_sixteenth: i32, faults: u32) -> [u8; 4]
+    {
+        let word = ((thermocouple_quarter as u32 & 0x3FFF) << 18) | ((cold_junction_sixteenth as u32 & 0x0FFF) << 4) | faults;
+        word.to_be_bytes()
+    }
+
+    // Examples in Table 2 and Table 4 of MAX31855 datasheet.
+    #[test]
+    fn max31855_thermocouple_range_ends()
+    {
+        let hot = decode_max31855([0x64, 0x00, 0x00, 0x00]).unwrap();
+        assert_eq!(hot.thermocouple, Temperature::from_centi(160000));
+
+        let cold = decode_max31855([0xF0, 0x60, 0x00, 0x00]).unwrap();
+        assert_eq!(cold.thermocouple, Temperature::from_centi(-25000));
+
+        assert_eq!(decode_max31855(max31855_frame(1, 0, 0)).unwrap().thermocouple, Temperature::from_centi(25));
+        assert_eq!(decode_max31855(max31855_frame(-1, 0, 0)).unwrap().thermocouple, Temperature::from_centi(-25));
+    }
+
+    #[test]
+    fn max31855_cold_junction()
+    {
+        // 0.0625 celsius is 6.25 centi-celsius, truncated to Temperature resolution.
+        let lsb = decode_max31855(max31855_frame(0, 1, 0)).unwrap();
+        assert_eq!(lsb.thermocouple, Temperature::from_centi(0));
+        assert_eq!(lsb.cold_junction, Some(Temperature::from_centi(6)));
+
+        let hot = decode_max31855(max31855_frame(0, 0x7F0, 0)).unwrap();
+        assert_eq!(hot.cold_junction, Some(Temperature::from_centi(12700)));
+
+        let cold = decode_max31855(max31855_frame(0, -880, 0)).unwrap();
+        assert_eq!(cold.cold_junction, Some(Temperature::from_centi(-5500)));
+    }
+
+    #[test]
+    fn max31855_faults()
+    {
+        assert_eq!(decode_max31855(max31855_frame(100, 400, MAX31855_FAULT_BIT | MAX31855_OC_BIT)), Err(SensorFault::Open));
+        assert_eq!(decode_max31855(max31855_frame(100, 400, MAX31855_FAULT_BIT | MAX31855_SCG_BIT)), Err(SensorFault::ShortToGnd));
+        assert_eq!(decode_max31855(max31855_frame(100, 400, MAX31855_FAULT_BIT | MAX31855_SCV_BIT)), Err(SensorFault::ShortToVcc));
+        // Fault flag without cause bit.
+        assert_eq!(decode_max31855(max31855_frame(100, 400, MAX31855_FAULT_BIT)), Err(SensorFault::Bus));
+    }
+
+    #[test]
+    fn max31855_zero_celsius()
+    {
+        let zero = decode_max31855([0x00; 4]).unwrap();
+        assert_eq!(zero.thermocouple, Temperature::from_centi(0));
+        assert_eq!(zero.cold_junction, Some(Temperature::from_centi(0)));
+    }
+
+    #[test]
+    fn max31855_stuck_bus()
+    {
+        assert_eq!(decode_max31855([0xFF; 4]), Err(SensorFault::Bus));
+        assert_eq!(decode_max31855(max31855_frame(100, 400, 1 << 17)), Err(SensorFault::Bus));
+        assert_eq!(decode_max31855(max31855_frame(100, 400, 1 << 3)), Err(SensorFault::Bus));
+    }
+
+    #[test]
+    fn max6675_decode()
+    {
+        assert_eq!(decode_max6675(((100u16) << 3).to_be_bytes()).unwrap().thermocouple, Temperature::from_centi(2500));
+        let max = decode_max6675([0x7F, 0xF8]).unwrap();
+        assert_eq!(max.thermocouple, Temperature::from_centi(102375));
+        assert_eq!(max.cold_junction, None);
+    }
+
+    #[test]
+    fn max6675_faults()
+    {
+        assert_eq!(decode_max6675(((100u16) << 3 | MAX6675_OPEN_BIT).to_be_bytes()), Err(SensorFault::Open));
+        assert_eq!(decode_max6675([0xFF; 2]), Err(SensorFault::Bus));
+        assert_eq!(decode_max6675(((100u16) << 3 | 1 << 1).to_be_bytes()), Err(SensorFault::Bus));
+    }
+
+    #[test]
+    fn max6675_zero_celsius()
+    {
+        assert_eq!(decode_max6675([0x00; 2]).unwrap().thermocouple, Temperature::from_centi(0));
+    }
+}
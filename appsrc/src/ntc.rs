@@ -0,0 +1,101 @@
+use alloc::string::String;
+use num_traits::float::Float;
+
+// ADC -> temperature conversion method of NTC thermistor channel.
+#[derive(Copy, Clone, PartialEq)]
+pub enum Conversion
+{
+    // Interpolate TEMPERATURE_TABLE generated at build time.
+    Table,
+    // 1/T = 1/T25 + ln(R/R25)/B
+    Beta { r25: f32, beta: f32 },
+    // 1/T = a + b*ln(R) + c*ln(R)^3
+    SteinhartHart { a: f32, b: f32, c: f32 },
+}
+
+// divider_supply --- NTC --- ADC input --- series_resistor --- GND
+#[derive(Copy, Clone, PartialEq)]
+pub struct DividerCircuit
+{
+    pub series_resistor : f32,
+    pub supply_voltage : f32,
+    pub adc_reference_voltage : f32,
+    pub adc_full_scale : f32,
+}
+
+const KELVIN_AT_ZERO_CELCIUS : f32 = 273.15;
+const KELVIN_AT_25_CELCIUS : f32 = 298.15;
+// Formula result out of this range is treated as invalid parameter.
+const FORMULA_TEMPERATURE_MIN : f32 = -100.0;
+const FORMULA_TEMPERATURE_MAX : f32 = 400.0;
+
+impl Conversion
+{
+    pub fn name(&self) -> &'static str
+    {
+        match self {
+            Conversion::Table => "table",
+            Conversion::Beta { .. } => "beta",
+            Conversion::SteinhartHart { .. } => "steinhart-hart",
+        }
+    }
+
+    // Check parameters are usable for formula.
+    pub fn validate(&self) -> Result<(), String>
+    {
+        match *self {
+            Conversion::Table => Ok(()),
+            Conversion::Beta { r25, beta } => {
+                if !r25.is_finite() || r25 <= 0.0 {
+                    return Err(String::from("r25 must be positive"));
+                }
+                if !beta.is_finite() || beta <= 0.0 {
+                    return Err(String::from("beta must be positive"));
+                }
+                Ok(())
+            }
+            Conversion::SteinhartHart { a, b, c } => {
+                if !a.is_finite() || !b.is_finite() || !c.is_finite() {
+                    return Err(String::from("a, b and c must be finite"));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl DividerCircuit
+{
+    // NTC resistance[ohm] at ADC count.
+    pub fn resistance(&self, adc_value: u16) -> f32
+    {
+        // Clip to 1..full_scale-1, same range as conversion table has valid records.
+        let adc = (adc_value as f32).max(1.0).min(self.adc_full_scale - 1.0);
+        let voltage = (adc * self.adc_reference_voltage / self.adc_full_scale).min(self.supply_voltage * 0.9999);
+
+        self.series_resistor * (self.supply_voltage / voltage - 1.0)
+    }
+}
+
+// Calculate temperature[Celsius] with formula.
+// Returns None for Conversion::Table, and when result is not finite or out of sane range.
+pub fn formula_temperature(conversion: &Conversion, circuit: &DividerCircuit, adc_value: u16) -> Option<f32>
+{
+    let resistance = circuit.resistance(adc_value);
+
+    let inv_kelvin = match *conversion {
+        Conversion::Table => return None,
+        Conversion::Beta { r25, beta } => 1.0 / KELVIN_AT_25_CELCIUS + (resistance / r25).ln() / beta,
+        Conversion::SteinhartHart { a, b, c } => {
+            let ln_r = resistance.ln();
+            a + b * ln_r + c * ln_r * ln_r * ln_r
+        }
+    };
+
+    let temperature = 1.0 / inv_kelvin - KELVIN_AT_ZERO_CELCIUS;
+    if !temperature.is_finite() || !(FORMULA_TEMPERATURE_MIN..=FORMULA_TEMPERATURE_MAX).contains(&temperature) {
+        return None;
+    }
+
+    Some(temperature)
+}
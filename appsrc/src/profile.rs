@@ -0,0 +1,133 @@
+use alloc::string::String;
+
+use crate::storage::*;
+use crate::thermometer::*;
+use crate::ntc::*;
+
+//
+// static const variables
+//
+pub const THERMISTOR_PROFILE_NUM : usize = 5;
+// [kind(1)][param a(4)][param b(4)][param c(4)]
+const PROFILE_RECORD_SIZE : usize = 13;
+const PROFILE_KIND_TABLE : u8 = 0;
+const PROFILE_KIND_BETA : u8 = 1;
+const PROFILE_KIND_STEINHART_HART : u8 = 2;
+
+// Thermistor profile. Conversion which matches no profile is shown as "custom".
+#[derive(Copy, Clone)]
+pub struct ThermistorProfile
+{
+    pub name : &'static str,
+    pub conversion : Conversion,
+}
+
+// Built-in profiles. Series resistor and ADC reference are board constants from thermistor.toml.
+pub fn thermistor_profiles() -> [ThermistorProfile; THERMISTOR_PROFILE_NUM]
+{
+    [
+        // Generated at build time from thermistor.toml
+        ThermistorProfile { name: "builtin-table", conversion: Conversion::Table },
+        ThermistorProfile { name: "builtin-formula", conversion: default_formula() },
+        ThermistorProfile { name: "10k-b3435", conversion: Conversion::Beta { r25: 10000.0, beta: 3435.0 } },
+        ThermistorProfile { name: "10k-b3950", conversion: Conversion::Beta { r25: 10000.0, beta: 3950.0 } },
+        ThermistorProfile { name: "100k-b3950", conversion: Conversion::Beta { r25: 100000.0, beta: 3950.0 } },
+    ]
+}
+
+// Name of profile which channel currently uses.
+pub fn profile_name(channel: SensorChannel) -> &'static str
+{
+    let conversion = conversion(channel);
+    thermistor_profiles().iter()
+        .find(|profile| profile.conversion == conversion)
+        .map_or("custom", |profile| profile.name)
+}
+
+pub fn select_profile(channel: SensorChannel, name: &str) -> Result<Conversion, String>
+{
+    let profile = thermistor_profiles().iter()
+        .find(|profile| profile.name == name)
+        .copied()
+        .ok_or(String::from("unknown profile"))?;
+
+    set_channel_conversion(channel, profile.conversion)
+}
+
+// Select conversion of channel. Error is invalid request only, call save_profiles() so probe change survives reboot.
+pub fn set_channel_conversion(channel: SensorChannel, conversion: Conversion) -> Result<Conversion, String>
+{
+    if !channel.is_thermistor() {
+        return Err(String::from("sensor channel has no conversion setting"));
+    }
+    set_conversion(channel, conversion);
+
+    Ok(conversion)
+}
+
+pub fn load_profiles()
+{
+    let mut buf = [0u8; PROFILE_RECORD_SIZE * SENSOR_CHANNEL_NUM];
+    match storage_read(StorageSlot::ThermistorProfile, &mut buf) {
+        Ok(n) if n <= buf.len() && n % PROFILE_RECORD_SIZE == 0 => {
+            for (channel, record) in SensorChannel::all().iter().zip(buf[..n].chunks(PROFILE_RECORD_SIZE)) {
+                match decode_conversion(record) {
+                    Some(conversion) if channel.is_thermistor() => set_conversion(*channel, conversion),
+                    _ => {}
+                }
+            }
+            log::info!("Thermistor profiles loaded.");
+        }
+        Ok(n) => {
+            log::warn!("Thermistor profiles have unexpected length: {}", n);
+        }
+        Err(e) => {
+            // Not configured yet. Use build time conversion table.
+            log::warn!("Thermistor profiles not loaded: {}", e.as_str());
+        }
+    }
+}
+
+pub fn save_profiles() -> Result<(), String>
+{
+    let mut buf = [0u8; PROFILE_RECORD_SIZE * SENSOR_CHANNEL_NUM];
+    for (channel, record) in SensorChannel::all().iter().zip(buf.chunks_mut(PROFILE_RECORD_SIZE)) {
+        encode_conversion(&conversion(*channel), record);
+    }
+
+    storage_write(StorageSlot::ThermistorProfile, &buf)
+}
+
+fn encode_conversion(conversion: &Conversion, record: &mut [u8])
+{
+    let (kind, params) = match *conversion {
+        Conversion::Table => (PROFILE_KIND_TABLE, [0.0f32; 3]),
+        Conversion::Beta { r25, beta } => (PROFILE_KIND_BETA, [r25, beta, 0.0]),
+        Conversion::SteinhartHart { a, b, c } => (PROFILE_KIND_STEINHART_HART, [a, b, c]),
+    };
+
+    record[0] = kind;
+    for (param, bytes) in params.iter().zip(record[1..].chunks_mut(4)) {
+        bytes.copy_from_slice(&param.to_le_bytes());
+    }
+}
+
+fn decode_conversion(record: &[u8]) -> Option<Conversion>
+{
+    let param = |i: usize| f32::from_le_bytes([record[1 + i * 4], record[2 + i * 4], record[3 + i * 4], record[4 + i * 4]]);
+
+    let conversion = match record[0] {
+        PROFILE_KIND_TABLE => Conversion::Table,
+        PROFILE_KIND_BETA => Conversion::Beta { r25: param(0), beta: param(1) },
+        PROFILE_KIND_STEINHART_HART => Conversion::SteinhartHart { a: param(0), b: param(1), c: param(2) },
+        _ => return None,
+    };
+
+    // Same check as POST /sensors/{channel}/conversion, broken record falls back to table.
+    if let Err(e) = conversion.validate() {
+        log::warn!("Stored thermistor profile is invalid: {}", e);
+        return Some(Conversion::Table);
+    }
+
+    Some(conversion)
+}
@@ -1,13 +1,35 @@
+use core::cell::RefCell;
+use core::fmt::Write as _;
 use core::str::from_utf8;
 
 use embassy_time::Timer;
 use embassy_time::Duration;
+use embassy_time::Instant;
+use embassy_time::with_timeout;
 use embassy_net::tcp::TcpSocket;
+use embassy_sync::blocking_mutex::{Mutex, raw::ThreadModeRawMutex};
 use embedded_io::asynch::Write;
-use alloc::string::{String, ToString};
+use alloc::string::String;
+use alloc::vec::Vec;
+use serde::{Serialize, Deserialize};
 
 use crate::thermometer::*;
+use crate::current::*;
 use crate::controller::*;
+use crate::gpio::*;
+use crate::diagnostics::*;
+use crate::energy::*;
+use crate::calibration::*;
+use crate::ntc::*;
+use crate::filter::*;
+use crate::history::*;
+use crate::profile::*;
+use crate::temperature::*;
+use crate::stats::*;
+use crate::rest_schema::*;
+use crate::websocket::*;
+#[cfg(feature = "wifi-rssi")]
+use crate::wifi::*;
 
 pub struct Rest<'a>
 {
@@ -16,67 +38,386 @@ pub struct Rest<'a>
     next_stream_head: usize,
 }
 
+struct RestResponse
+{
+    header : String,
+    body : ResponseBody,
+    // Length of request in receive buffer, answered by this response.
+    consumed : usize,
+    // Connection is kept for next request.
+    keep_alive : bool,
+    // Some if connection is switched to stream.
+    stream : Option<Stream>,
+}
+
+// Connection switched from HTTP, holds worker until client goes away.
+enum Stream
+{
+    // GET /events/stream, "text/event-stream".
+    Events(Telemetry),
+    // GET /ws, upgraded to WebSocket.
+    WebSocket(Telemetry),
+}
+
+// Parameters of telemetry pushed by stream.
+struct Telemetry
+{
+    // Period of "temperature" event.
+    interval : Duration,
+    unit : TemperatureUnit,
+}
+
+// Decides which telemetry is sent on each poll of stream.
+struct TelemetrySchedule
+{
+    telemetry : Telemetry,
+    // state, err_code and warnings last sent.
+    last_status : Option<(&'static str, u32, Vec<&'static str>)>,
+    next_sample : Instant,
+}
+
+// Prometheus text written family by family into response body.
+struct MetricsBody
+{
+    body : ResponseBody,
+    // Family being written, not yet in body.
+    family : String,
+    // Families which did not fit.
+    dropped : u32,
+}
+
+enum TelemetryEvent
+{
+    Status(StatusResponse),
+    Temperature(DetailsResponse),
+}
+
+// What to do for frame received by WebSocket.
+enum WebSocketAction
+{
+    None,
+    Send(Opcode, Vec<u8>),
+    // Close requested by client.
+    Close(u16),
+    // Close by error.
+    Fail(u16, String),
+}
+
+// Failed request. Sent with HTTP status code and {"error":{"status":..,"message":..}} body.
+enum HttpError
+{
+    // Malformed request or invalid parameter.
+    BadRequest(String),
+    NotFound,
+    // Path exists, but not for this method. Holds value of "Allow" header.
+    MethodNotAllowed(&'static str),
+    // Request does not fit in receive buffer.
+    PayloadTooLarge,
+    // Failure on our side, e.g. flash write error.
+    Internal(String),
+    // All stream slots are in use.
+    ServiceUnavailable,
+}
+
+//
+// static const variables
+//
+// Number of rest_task workers, each serves one connection at a time.
+pub const REST_WORKER_NUM : usize = 3;
+pub const REST_SOCKET_BUFFER_SIZE : usize = 2048;
+// Connection is aborted if peer sends nothing for this time.
+const REST_SOCKET_TIMEOUT_S : u64 = 10;
+// Keep-alive connection is closed if next request does not come in this time.
+// Must be shorter than REST_SOCKET_TIMEOUT_S.
+const REST_IDLE_TIMEOUT_S : u64 = 5;
+// Stream sends temperature every interval ms, "interval" query parameter can change it.
+const STREAM_INTERVAL_DEFAULT_MS : u64 = 1000;
+const STREAM_INTERVAL_MIN_MS : u64 = 200;
+const STREAM_INTERVAL_MAX_MS : u64 = 60000;
+// Status and fault are checked in this period, and pushed as soon as they change.
+const STREAM_POLL_MS : u64 = 100;
+// Event stream sends comment line if nothing sent in this time, WebSocket sends ping every this time.
+// Write to dead client fails by socket timeout.
+// Must be shorter than REST_SOCKET_TIMEOUT_S, otherwise idle stream is aborted.
+const STREAM_HEARTBEAT_S : u64 = 5;
+// EventSource reconnects after this time when connection is lost.
+const EVENT_STREAM_RETRY_MS : u64 = 3000;
+// WebSocket is closed if client does not send anything, even pong, in this number of pings.
+const WEBSOCKET_PING_LOST_MAX : u64 = 3;
+// Stream holds worker until client goes away, keep one worker for other requests.
+const STREAM_MAX : usize = REST_WORKER_NUM - 1;
+// Give up waiting peer's FIN/ACK after this time.
+const REST_CLOSE_TIMEOUT_MS : u64 = 2000;
+
+const CONTENT_TYPE_JSON : &str = "application/json; charset=utf-8";
+// Prometheus text exposition format.
+const CONTENT_TYPE_METRICS : &str = "text/plain; version=0.0.4; charset=utf-8";
+// Status codes counted by reginheater_http_requests_total.
+const HTTP_STATUS_CODES : [u16; 8] = [101, 200, 400, 404, 405, 413, 500, 503];
+// Every name returned by current_status_name().
+const CONTROLLER_STATE_NAMES : [&str; 5] = ["Initializing", "Heating", "Saturating", "Stopped", "Error"];
+
+// Response body is serialized into fixed size buffer.
+const RESPONSE_BODY_SIZE : usize = 4096;
+type ResponseBody = heapless::Vec<u8, RESPONSE_BODY_SIZE>;
+// Space kept in metrics body for reginheater_metrics_dropped_families.
+const METRICS_RESERVED_SIZE : usize = 256;
+
+// Paths served by GET.
+const GET_PATHS : [&str; 21] = [
+    "/temperature/heater", "/temperature/cpu", "/temperature/ambient", "/temperature/all",
+    "/heater/power", "/status", "/details", "/diagnostics", "/energy", "/calibration",
+    "/sensors/conversion", "/sensors/filter", "/sensors/profiles", "/sensors/raw",
+    "/history", "/settings/unit", "/stats", "/cure/summary", "/events/stream",
+    "/ws", "/metrics",
+];
+// Paths served by POST, other than "/calibration/..." and "/sensors/{channel}/...".
+const POST_PATHS : [&str; 5] = ["/control/ramp", "/settings/unit", "/stats/config", "/cure/start", "/energy/reset"];
+
+//
+// static variables
+//
+static ACTIVE_CONNECTIONS : Mutex<ThreadModeRawMutex, RefCell<usize>> = Mutex::new(RefCell::new(0));
+static ACTIVE_STREAMS : Mutex<ThreadModeRawMutex, RefCell<usize>> = Mutex::new(RefCell::new(0));
+// Indexed same as HTTP_STATUS_CODES.
+static HTTP_REQUESTS : Mutex<ThreadModeRawMutex, RefCell<[u32; HTTP_STATUS_CODES.len()]>> = Mutex::new(RefCell::new([0; HTTP_STATUS_CODES.len()]));
+
 impl<'a> Rest<'a>
 {
     pub fn new(sock: TcpSocket<'a>) -> Self {
+        let mut sock = sock;
+        // Stalled client must not hold worker forever.
+        sock.set_timeout(Some(Duration::from_secs(REST_SOCKET_TIMEOUT_S)));
         Self { socket: sock, buf: [0; 4096], next_stream_head: 0 }
     }
 
     pub async fn do_rest_service(&mut self) -> Result<&mut Rest<'a>, String>
     {            
         loop {
-            // Read all data until complete parsing.
-            let readlen = match self.socket.read(&mut self.buf[self.next_stream_head..]).await {
-                Ok(0) => {
+            // Answer all complete requests in buffer. Client may send several requests without waiting response (pipelining).
+            while let Some(response) = create_rest_response(&self.buf[..self.next_stream_head], self.buf.len()).await {
+                if let Some(stream) = response.stream {
+                    // Rest of buffer belongs to stream, e.g. WebSocket frame sent right after handshake.
+                    self.buf.copy_within(response.consumed..self.next_stream_head, 0);
+                    self.next_stream_head -= response.consumed;
+
+                    // Stream holds this connection until client goes away.
+                    let result = match stream {
+                        Stream::Events(telemetry) => self.serve_event_stream(response.header.as_str(), telemetry).await,
+                        Stream::WebSocket(telemetry) => self.serve_websocket(response.header.as_str(), telemetry).await,
+                    };
+                    stream_closed();
+                    return match result {
+                        Ok(()) => Ok(self),
+                        Err(e) => Err(e),
+                    };
+                }
+
+                let written = match self.socket.write_all(response.header.as_bytes()).await {
+                    Ok(()) => self.socket.write_all(&response.body).await,
+                    Err(e) => Err(e),
+                };
+                match written {
+                    Ok(()) => {
+                        // response complete!
+                        log::info!("REST Response succeeded: TCPstatus[{}]\n{}{}", 
+                            get_tcp_state_string(self.socket.state()).as_str(), 
+                            response.header.as_str(),
+                            from_utf8(&response.body).unwrap_or("")
+                        );
+                    }
+                    Err(e) => {
+                        return Err(format!("write error: {:?}", e));
+                    }
+                };
+                match self.socket.flush().await {
+                    Ok(()) => {
+                        log::info!("Flush write buffer of socket.");
+                    }
+                    Err(e) => {
+                        return Err(format!("flush error {:?}", e));
+                    }
+                }
+
+                if !response.keep_alive {
+                    return Ok(self);
+                }
+                // Move rest of stream (next request) to head of buffer.
+                self.buf.copy_within(response.consumed..self.next_stream_head, 0);
+                self.next_stream_head -= response.consumed;
+            }
+
+            // Read until next request complete.
+            let read = with_timeout(
+                Duration::from_secs(REST_IDLE_TIMEOUT_S),
+                self.socket.read(&mut self.buf[self.next_stream_head..])
+            ).await;
+            let readlen = match read {
+                // Client closed connection between requests.
+                Ok(Ok(0)) if self.next_stream_head == 0 => {
+                    log::info!("Connection closed by peer.");
+                    return Ok(self);
+                }
+                Ok(Ok(0)) => {
                     return Err(String::from("Read EOF"));
                 }
-                Ok(n) => n,
-                Err(e) => {
+                Ok(Ok(n)) => n,
+                Ok(Err(e)) => {
                     return Err(format!("Read error: {:?}", e));
                 }
+                Err(_) if self.next_stream_head == 0 => {
+                    log::info!("Idle timeout, close connection.");
+                    return Ok(self);
+                }
+                Err(_) => {
+                    return Err(String::from("Request timeout"));
+                }
             };
 
-            log::info!( "do_rest_service(), Receive data: \n{}", from_utf8(&self.buf[..readlen]).unwrap_or("") );
-
-            let request_end = self.next_stream_head + readlen;
-            let result = create_rest_response(&self.buf[..request_end]);
-            match result.await {
-                Ok(opt) => {
-                    // complete parsing packet?
-                    if let Some(json_response) = opt {
-                        // OK, parsing complete and get response.
-                        match self.socket.write_all(&json_response.as_bytes()).await {
-                            Ok(()) => {
-                                // response complete!
-                                log::info!("REST Response succeeded: TCPstatus[{}]\n{}", 
-                                    get_tcp_state_string(self.socket.state()).as_str(), 
-                                    json_response.as_str()
-                                );
-                            }
-                            Err(e) => {
-                                return Err(format!("write error: {:?}", e));
-                            }
-                        };
-                        match self.socket.flush().await {
-                            Ok(()) => {
-                                log::info!("Flush write buffer of socket.");
-                                break;
-                            }
-                            Err(e) => {
-                                return Err(format!("flush error {:?}", e));
-                            }
-                        }
+            log::info!( "do_rest_service(), Receive data: \n{}", 
+                from_utf8(&self.buf[self.next_stream_head..self.next_stream_head + readlen]).unwrap_or("") 
+            );
+            self.next_stream_head += readlen;
+        }
+    }
+
+    async fn serve_event_stream(&mut self, header: &str, telemetry: Telemetry) -> Result<(), String>
+    {
+        log::info!("Start event stream, interval {} ms.", telemetry.interval.as_millis());
+        self.send(header.as_bytes()).await?;
+        self.send(format!("retry: {}\n\n", EVENT_STREAM_RETRY_MS).as_bytes()).await?;
+
+        let mut schedule = TelemetrySchedule::new(telemetry);
+        let mut next_heartbeat = Instant::now() + Duration::from_secs(STREAM_HEARTBEAT_S);
+        loop {
+            // Client closed EventSource.
+            if self.socket.state() != embassy_net::tcp::State::Established {
+                log::info!("Event stream closed by peer.");
+                return Ok(());
+            }
+
+            let events = schedule.poll();
+            for event in events.iter() {
+                let (name, body) = match event {
+                    TelemetryEvent::Status(status) => ("status", to_body(status)),
+                    TelemetryEvent::Temperature(details) => ("temperature", to_body(details)),
+                };
+                self.send_event(name, &body.map_err(|e| e.message())?).await?;
+            }
+            if !events.is_empty() {
+                next_heartbeat = Instant::now() + Duration::from_secs(STREAM_HEARTBEAT_S);
+            }
+            else if Instant::now() >= next_heartbeat {
+                self.send(b": heartbeat\n\n").await?;
+                next_heartbeat = Instant::now() + Duration::from_secs(STREAM_HEARTBEAT_S);
+            }
+
+            Timer::after(Duration::from_millis(STREAM_POLL_MS)).await;
+        }
+    }
+
+    async fn serve_websocket(&mut self, header: &str, telemetry: Telemetry) -> Result<(), String>
+    {
+        log::info!("Start WebSocket, interval {} ms.", telemetry.interval.as_millis());
+        self.send(header.as_bytes()).await?;
+
+        let unit = telemetry.unit;
+        let mut schedule = TelemetrySchedule::new(telemetry);
+        let mut next_ping = Instant::now() + Duration::from_secs(STREAM_HEARTBEAT_S);
+        let mut last_received = Instant::now();
+        loop {
+            // Handle all complete frames in buffer.
+            loop {
+                let frame = match parse_frame_header(&self.buf[..self.next_stream_head], self.buf.len()) {
+                    Ok(Some(frame)) => frame,
+                    Ok(None) => break,
+                    Err((code, reason)) => return self.fail_websocket(code, reason).await,
+                };
+                if frame.frame_len() > self.next_stream_head {
+                    break;
+                }
+                let mask = match frame.mask {
+                    Some(mask) => mask,
+                    None => return self.fail_websocket(CLOSE_PROTOCOL_ERROR, String::from("client frame is not masked")).await,
+                };
+
+                let payload = &mut self.buf[frame.header_len..frame.frame_len()];
+                apply_mask(payload, mask);
+                let action = websocket_action(&frame, payload, unit);
+                self.buf.copy_within(frame.frame_len()..self.next_stream_head, 0);
+                self.next_stream_head -= frame.frame_len();
+                last_received = Instant::now();
+
+                match action {
+                    WebSocketAction::None => {}
+                    WebSocketAction::Send(opcode, payload) => self.send_frame(opcode, &payload).await?,
+                    WebSocketAction::Close(code) => {
+                        log::info!("WebSocket closed by peer, code {}.", code);
+                        return self.send_frame(Opcode::Close, &close_payload(code)).await;
                     }
-                    // parsing incomplete. reset head for next read.
-                    self.next_stream_head += readlen;
+                    WebSocketAction::Fail(code, reason) => return self.fail_websocket(code, reason).await,
                 }
-                Err(e) => {
-                    return Err(format!("REST internal error: {:?}", e.as_str()));
+            }
+
+            for event in schedule.poll() {
+                let body = match event {
+                    TelemetryEvent::Status(status) => to_body(&WebSocketEvent { event: "status", data: status }),
+                    TelemetryEvent::Temperature(details) => to_body(&WebSocketEvent { event: "temperature", data: details }),
+                };
+                self.send_frame(Opcode::Text, &body.map_err(|e| e.message())?).await?;
+            }
+            if Instant::now() >= next_ping {
+                self.send_frame(Opcode::Ping, &[]).await?;
+                next_ping = Instant::now() + Duration::from_secs(STREAM_HEARTBEAT_S);
+            }
+            // Client must answer ping by pong.
+            if Instant::now() >= last_received + Duration::from_secs(STREAM_HEARTBEAT_S * WEBSOCKET_PING_LOST_MAX) {
+                return self.fail_websocket(CLOSE_GOING_AWAY, String::from("no response from peer")).await;
+            }
+
+            // Wait frame from client, or next poll.
+            let read = with_timeout(
+                Duration::from_millis(STREAM_POLL_MS),
+                self.socket.read(&mut self.buf[self.next_stream_head..])
+            ).await;
+            match read {
+                Err(_) => {}
+                Ok(Ok(0)) => {
+                    log::info!("WebSocket closed by peer without close frame.");
+                    return Ok(());
                 }
-            };
+                Ok(Ok(n)) => self.next_stream_head += n,
+                Ok(Err(e)) => return Err(format!("Read error: {:?}", e)),
+            }
         }
-        
-        Ok(self)
+    }
+
+    // Send close frame with status code, and report reason as error.
+    async fn fail_websocket(&mut self, code: u16, reason: String) -> Result<(), String>
+    {
+        self.send_frame(Opcode::Close, &close_payload(code)).await?;
+        Err(format!("WebSocket closed with {}: {}", code, reason))
+    }
+
+    async fn send_frame(&mut self, opcode: Opcode, payload: &[u8]) -> Result<(), String>
+    {
+        let header = encode_frame_header(opcode, payload.len());
+        self.socket.write_all(&header).await.map_err(|e| format!("write error: {:?}", e))?;
+        self.send(payload).await
+    }
+
+    async fn send_event(&mut self, event: &str, data: &[u8]) -> Result<(), String>
+    {
+        // JSON body has no newline, so it fits in one "data:" line.
+        self.send(format!("event: {}\ndata: ", event).as_bytes()).await?;
+        self.send(data).await?;
+        self.send(b"\n\n").await
+    }
+
+    async fn send(&mut self, data: &[u8]) -> Result<(), String>
+    {
+        self.socket.write_all(data).await.map_err(|e| format!("write error: {:?}", e))?;
+        self.socket.flush().await.map_err(|e| format!("flush error {:?}", e))
     }
 
     pub async fn accept(&mut self) -> Result<(), String>
@@ -86,6 +427,7 @@ impl<'a> Rest<'a>
         match self.socket.accept(80).await {
             Ok(()) => {
                 log::info!("Received connection from {:?}", self.socket.remote_endpoint());
+                connection_opened();
                 return Ok(());
             }
             Err(e) => {
@@ -100,6 +442,7 @@ impl<'a> Rest<'a>
         // FIN packet will NOT send.
         // So we should wait until FIN packet sent, by checking TCP state be "CLOSED" or "TIMEWAIT".
         self.socket.close();
+        let deadline = Instant::now() + Duration::from_millis(REST_CLOSE_TIMEOUT_MS);
         loop {
             log::info!("Closing socket... TCPstatus[{}]", 
                 get_tcp_state_string(self.socket.state()).as_str()
@@ -110,133 +453,1386 @@ impl<'a> Rest<'a>
                 log::info!("Close socket successfuly.");
                 break;
             }
+            // Peer does not finish close sequence. Reset connection, worker must go back to listen.
+            if Instant::now() >= deadline {
+                log::warn!("Close socket timeout, abort connection.");
+                self.socket.abort();
+                break;
+            }
             Timer::after(Duration::from_millis(100)).await;
         }
+        connection_closed();
+    }
+
+}
+
+// Number of connections being served by rest_task workers.
+pub fn active_connections() -> usize
+{
+    ACTIVE_CONNECTIONS.lock(|lock| {
+        *(lock.borrow_mut())
+    })
+}
+
+fn connection_opened()
+{
+    ACTIVE_CONNECTIONS.lock(|lock| {
+        *lock.borrow_mut() += 1
+    });
+}
+
+fn connection_closed()
+{
+    ACTIVE_CONNECTIONS.lock(|lock| {
+        let mut count = lock.borrow_mut();
+        *count = count.saturating_sub(1)
+    });
+}
+
+// Number of clients connected to /events/stream or /ws.
+pub fn active_streams() -> usize
+{
+    ACTIVE_STREAMS.lock(|lock| {
+        *(lock.borrow_mut())
+    })
+}
+
+// Take one stream slot, false if all slots are in use.
+fn stream_opened() -> bool
+{
+    ACTIVE_STREAMS.lock(|lock| {
+        let mut count = lock.borrow_mut();
+        if *count >= STREAM_MAX {
+            return false;
+        }
+        *count += 1;
+        true
+    })
+}
+
+fn stream_closed()
+{
+    ACTIVE_STREAMS.lock(|lock| {
+        let mut count = lock.borrow_mut();
+        *count = count.saturating_sub(1)
+    });
+}
+
+fn count_http_request(status: u16)
+{
+    if let Some(index) = HTTP_STATUS_CODES.iter().position(|&code| code == status) {
+        HTTP_REQUESTS.lock(|lock| {
+            lock.borrow_mut()[index] += 1;
+        });
+    }
+}
+
+// Indexed same as HTTP_STATUS_CODES.
+fn http_requests() -> [u32; HTTP_STATUS_CODES.len()]
+{
+    HTTP_REQUESTS.lock(|lock| {
+        *(lock.borrow_mut())
+    })
+}
+
+impl HttpError
+{
+    // Status code and reason phrase.
+    fn status(&self) -> (u16, &'static str)
+    {
+        match self {
+            HttpError::BadRequest(_) => (400, "Bad Request"),
+            HttpError::NotFound => (404, "Not Found"),
+            HttpError::MethodNotAllowed(_) => (405, "Method Not Allowed"),
+            HttpError::PayloadTooLarge => (413, "Payload Too Large"),
+            HttpError::Internal(_) => (500, "Internal Server Error"),
+            HttpError::ServiceUnavailable => (503, "Service Unavailable"),
+        }
     }
 
+    fn message(&self) -> String
+    {
+        match self {
+            HttpError::BadRequest(message) => message.clone(),
+            HttpError::NotFound => String::from("invalid request path"),
+            HttpError::MethodNotAllowed(allow) => format!("method not allowed, use {}", allow),
+            HttpError::PayloadTooLarge => String::from("request too large"),
+            HttpError::Internal(message) => message.clone(),
+            HttpError::ServiceUnavailable => String::from("too many streams"),
+        }
+    }
+
+    fn allow(&self) -> Option<&'static str>
+    {
+        match self {
+            HttpError::MethodNotAllowed(allow) => Some(*allow),
+            _ => None,
+        }
+    }
 }
 
-async fn create_rest_response(buf: &[u8]) -> Result<Option<String>, String>
+// Response text of request in buf, None if request is not received completely yet.
+// capacity is size of receive buffer, request larger than it is answered with 413.
+async fn create_rest_response(buf: &[u8], capacity: usize) -> Option<RestResponse>
 {
     let mut headers = [httparse::EMPTY_HEADER; 64];
     let mut request = httparse::Request::new(&mut headers);
-    
-    let status = request.parse(buf).map_err( |e| { format!("HTTP Header parsing error: {}",e) } )?;
-    if status.is_partial() {
+
+    let (result, consumed, keep_alive) = match parse_rest_request(&mut request, buf, capacity) {
+        Ok(None) => return None,
+        Ok(Some((header_len, body_end))) => {
+            // Request boundary is known, so connection can be kept even if request failed.
+            match stream_request(&request, body_end) {
+                Some(Ok(response)) => return Some(response),
+                Some(Err(e)) => (Err(e), body_end, is_keep_alive(&request)),
+                None => (handle_request(&request, &buf[header_len..body_end]), body_end, is_keep_alive(&request)),
+            }
+        }
+        // Cannot find where next request starts, connection must be closed.
+        Err(e) => (Err(e), buf.len(), false),
+    };
+
+    match result {
+        Ok((body, content_type)) => {
+            count_http_request(200);
+            let header = create_header_text(body.len(), content_type, None, keep_alive);
+            Some(RestResponse { header: format!("HTTP/1.1 200 OK\r\n{}\r\n", header), body: body, consumed: consumed, keep_alive: keep_alive, stream: None })
+        }
+        Err(e) => {
+            let (status, reason) = e.status();
+            let message = e.message();
+            log::warn!("REST request failed: {} {}", status, message.as_str());
+            count_http_request(status);
+
+            // Error body is small, it always fits in buffer.
+            let body = to_body(&ErrorResponse { error: ErrorBody { status: status, message: message.as_str() } }).unwrap_or_default();
+            let header = create_header_text(body.len(), CONTENT_TYPE_JSON, e.allow(), keep_alive);
+            Some(RestResponse { header: format!("HTTP/1.1 {} {}\r\n{}\r\n", status, reason, header), body: body, consumed: consumed, keep_alive: keep_alive, stream: None })
+        }
+    }
+}
+
+// Some if request opens stream, GET /events/stream or GET /ws. Takes stream slot when succeeded.
+fn stream_request<'a>(request: &httparse::Request<'a, 'a>, consumed: usize) -> Option<Result<RestResponse, HttpError>>
+{
+    let path = request.path?;
+    let (path, query) = path.split_once('?').unwrap_or((path, ""));
+    if request.method != Some("GET") {
+        return None;
+    }
+    let websocket = match path {
+        "/events/stream" => false,
+        "/ws" => true,
+        _ => return None,
+    };
+
+    Some(open_stream(request, query, websocket, consumed))
+}
+
+fn open_stream<'a>(request: &httparse::Request<'a, 'a>, query: &str, websocket: bool, consumed: usize) -> Result<RestResponse, HttpError>
+{
+    let unit = request_unit(query)?;
+    let interval_ms = match query_value(query, "interval").map(|v| v.parse::<u64>()) {
+        None => STREAM_INTERVAL_DEFAULT_MS,
+        Some(Ok(ms)) if (STREAM_INTERVAL_MIN_MS..=STREAM_INTERVAL_MAX_MS).contains(&ms) => ms,
+        Some(_) => return Err(HttpError::BadRequest(
+            format!("interval must be {}..{} ms", STREAM_INTERVAL_MIN_MS, STREAM_INTERVAL_MAX_MS)
+        )),
+    };
+    let telemetry = Telemetry { interval: Duration::from_millis(interval_ms), unit: unit };
+
+    // Stream has no content-length, it continues until connection closed.
+    let (header, stream) = if websocket {
+        let key = websocket_key(request)?;
+        let header = format!(
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+            accept_key(key)
+        );
+        (header, Stream::WebSocket(telemetry))
+    } else {
+        let header = String::from(
+            "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ncache-control: no-cache\r\nAccess-Control-Allow-Origin: *\r\n\r\n"
+        );
+        (header, Stream::Events(telemetry))
+    };
+    if !stream_opened() {
+        return Err(HttpError::ServiceUnavailable);
+    }
+    count_http_request(if websocket { 101 } else { 200 });
+
+    Ok(RestResponse { header: header, body: ResponseBody::new(), consumed: consumed, keep_alive: false, stream: Some(stream) })
+}
+
+// Sec-WebSocket-Key of upgrade request.
+fn websocket_key<'a>(request: &httparse::Request<'a, 'a>) -> Result<&'a str, HttpError>
+{
+    let has_token = |name: &str, token: &str| {
+        header_value(request, name).map_or(false, |v| v.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+    };
+    if !has_token("upgrade", "websocket") || !has_token("connection", "upgrade") {
+        return Err(HttpError::BadRequest(String::from("WebSocket upgrade is required")));
+    }
+    if header_value(request, "sec-websocket-version").map(|v| v.trim()) != Some("13") {
+        return Err(HttpError::BadRequest(String::from("Sec-WebSocket-Version must be 13")));
+    }
+
+    header_value(request, "sec-websocket-key").ok_or_else(|| HttpError::BadRequest(String::from("Sec-WebSocket-Key is required")))
+}
+
+fn header_value<'a>(request: &httparse::Request<'a, 'a>, name: &str) -> Option<&'a str>
+{
+    request.headers.iter()
+        .find(|h| h.name.eq_ignore_ascii_case(name))
+        .and_then(|h| from_utf8(h.value).ok())
+}
+
+// Parse header of first request in buffer.
+// Returns (header length, request length) if whole request received, None if more data is needed.
+fn parse_rest_request<'a>(request: &mut httparse::Request<'a, 'a>, buf: &'a [u8], capacity: usize) -> Result<Option<(usize, usize)>, HttpError>
+{
+    let status = request.parse(buf).map_err( |e| HttpError::BadRequest(format!("HTTP header parsing error: {}", e)) )?;
+    let header_len = match status {
+        httparse::Status::Complete(n) => n,
+        // Buffer is full but header is not complete yet.
+        httparse::Status::Partial if buf.len() >= capacity => return Err(HttpError::PayloadTooLarge),
+        httparse::Status::Partial => return Ok(None),
+    };
+
+    // Compare before adding, huge Content-Length must not overflow.
+    let content_length = content_length(request).map_err(HttpError::BadRequest)?;
+    if content_length > capacity.saturating_sub(header_len) as u64 {
+        return Err(HttpError::PayloadTooLarge);
+    }
+
+    // Wait until whole body received.
+    let body_end = header_len + content_length as usize;
+    if buf.len() < body_end {
         return Ok(None);
     }
 
-    response(&request).map(
-        |json| {
-            let body = format!("{{{}}}", json);
-            let header = create_header_text(body.len());
-            Some(format!("HTTP/1.1 200 OK\r\n{}\r\n{}", header, body))
-        }
-    )
+    Ok(Some((header_len, body_end)))
+}
+
+// Returns body and its content-type.
+fn handle_request<'a>(request: &httparse::Request<'a, 'a>, body: &[u8]) -> Result<(ResponseBody, &'static str), HttpError>
+{
+    let body = from_utf8(body).map_err( |_| HttpError::BadRequest(String::from("HTTP body is not UTF-8.")) )?;
+
+    response(request, body)
 }
 
-fn create_header_text(content_length: usize) -> String
+// HTTP/1.1 keeps connection unless "Connection: close", HTTP/1.0 closes unless "Connection: keep-alive".
+fn is_keep_alive<'a>(request: &httparse::Request<'a, 'a>) -> bool
+{
+    let connection = request.headers.iter()
+        .find(|h| h.name.eq_ignore_ascii_case("connection"))
+        .and_then(|h| from_utf8(h.value).ok())
+        .unwrap_or("");
+    let has_token = |token: &str| connection.split(',').any(|t| t.trim().eq_ignore_ascii_case(token));
+
+    match request.version {
+        Some(1) => !has_token("close"),
+        _       => has_token("keep-alive"),
+    }
+}
+
+fn create_header_text(content_length: usize, content_type: &str, allow: Option<&str>, keep_alive: bool) -> String
 {
     let content_length = format!("content-length: {}", content_length);
-    let content_type = "content-type: application/json; charset=utf-8";
+    let content_type = format!("content-type: {}", content_type);
     //let vary = "vary: Origin, Access-Control-Request-Method, Access-Control-Request-Headers";
     //let access_control = "access-control-allow-credentials: true";
     let access_control_origin = "Access-Control-Allow-Origin: *";
+    let connection = if keep_alive {
+        format!("Connection: keep-alive\r\nKeep-Alive: timeout={}", REST_IDLE_TIMEOUT_S)
+    } else {
+        String::from("Connection: close")
+    };
+    // Required by 405 Method Not Allowed.
+    let allow = allow.map_or(String::new(), |methods| format!("Allow: {}\r\n", methods));
+
+    return format!("{}\r\n{}\r\n{}\r\n{}\r\n{}", content_length, content_type, access_control_origin, connection, allow)
+}
+
+// u64 to tell too large value from invalid one on 32bit target.
+fn content_length<'a>(request: &httparse::Request<'a, 'a>) -> Result<u64, String>
+{
+    match request.headers.iter().find(|h| h.name.eq_ignore_ascii_case("content-length")) {
+        Some(header) => {
+            from_utf8(header.value).ok()
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .ok_or(String::from("Invalid content-length."))
+        }
+        None => Ok(0),
+    }
+}
+
+fn response<'a>(request: &httparse::Request<'a, 'a>, body: &str) -> Result<(ResponseBody, &'static str), HttpError>
+{
+    let method = request.method.ok_or_else(|| HttpError::BadRequest(String::from("Request method is not found.")))?;
+    let path = request.path.ok_or_else(|| HttpError::BadRequest(String::from("HTTP request path not found.")))?;
+    let (path, query) = path.split_once('?').unwrap_or((path, ""));
+
+    let allow = allowed_methods(path).ok_or(HttpError::NotFound)?;
+    if !allow.split(", ").any(|m| m == method) {
+        return Err(HttpError::MethodNotAllowed(allow));
+    }
 
-    return format!("{}\r\n{}\r\n{}\r\n", content_length, content_type, access_control_origin)
+    let unit = request_unit(query)?;
+
+    match (method, path) {
+        // Prometheus scrapes text format, not JSON.
+        ("GET", "/metrics") => { rest_response_metrics().map(|body| (body, CONTENT_TYPE_METRICS)) }
+        ("GET", _)  => { response_get(path, query, unit).map(|body| (body, CONTENT_TYPE_JSON)) }
+        ("POST", _) => { response_post(path, body, unit).map(|body| (body, CONTENT_TYPE_JSON)) }
+        _           => Err(HttpError::MethodNotAllowed(allow)),
+    }
+}
+
+// "unit" query parameter selects temperature unit of this request, default unit is used if omitted.
+fn request_unit(query: &str) -> Result<TemperatureUnit, HttpError>
+{
+    match query_value(query, "unit") {
+        Some(name) => TemperatureUnit::from_name(name).ok_or_else(|| HttpError::BadRequest(String::from("invalid unit"))),
+        None => Ok(default_unit()),
+    }
 }
 
-fn response<'a>(request: &httparse::Request<'a, 'a>) -> Result<String, String>
+// Value of "Allow" header for path, None if path is unknown.
+fn allowed_methods(path: &str) -> Option<&'static str>
 {
-    let method = request.method.ok_or("Request method is not found.")?;
-    match method {
-        "GET"  => { response_get(request) }
-        "POST" => Ok(r#"{"error":"Not implemented."}"#.to_string()),
-        _      => Ok(r#"{"error":"Invalid method."}"#.to_string()),
+    match (GET_PATHS.contains(&path), is_post_path(path)) {
+        (true, true) => Some("GET, POST"),
+        (true, false) => Some("GET"),
+        (false, true) => Some("POST"),
+        (false, false) => None,
     }
 }
 
-fn response_get<'a>(request: &httparse::Request<'a, 'a>) -> Result<String, String>
+fn is_post_path(path: &str) -> bool
 {
-    let path = request.path.ok_or("HTTP request path not found.")?;
+    let sensor_setting = path.strip_prefix("/sensors/").map_or(false, |p| {
+        p.ends_with("/conversion") || p.ends_with("/profile") || p.ends_with("/filter")
+    });
+
+    POST_PATHS.contains(&path) || path.starts_with("/calibration/") || sensor_setting
+}
 
+fn response_get(path: &str, query: &str, unit: TemperatureUnit) -> Result<ResponseBody, HttpError>
+{
     match path {
         "/temperature/heater" => {
-            rest_response_temperature_heater()
+            rest_response_temperature_heater(unit)
         }
         "/temperature/cpu" => {
-            rest_response_temperature_cpu()
+            rest_response_temperature_cpu(unit)
+        }
+        "/temperature/ambient" => {
+            rest_response_temperature_ambient(unit)
         }
         "/temperature/all" => {
-            rest_response_temperature_all()
+            rest_response_temperature_all(unit)
+        }
+        "/heater/power" => {
+            rest_response_heater_power()
         }
         "/status" => {
-            rest_response_status()
+            rest_response_status(unit)
         }
         "/details" => {
-            rest_response_details()
+            rest_response_details(unit)
+        }
+        "/diagnostics" => {
+            rest_response_diagnostics()
+        }
+        "/energy" => {
+            rest_response_energy()
+        }
+        "/calibration" => {
+            rest_response_calibration(unit)
+        }
+        "/sensors/conversion" => {
+            rest_response_sensors_conversion()
+        }
+        "/sensors/filter" => {
+            rest_response_sensors_filter(unit)
         }
-        _ => { 
-            Ok(r#"{"error":"invalid request"}"#.to_string())
+        "/sensors/profiles" => {
+            rest_response_sensors_profiles()
+        }
+        "/sensors/raw" => {
+            rest_response_sensors_raw(unit)
+        }
+        "/history" => {
+            rest_response_history(query, unit)
+        }
+        "/settings/unit" => {
+            rest_response_settings_unit()
+        }
+        "/stats" => {
+            rest_response_stats(unit)
+        }
+        "/cure/summary" => {
+            rest_response_cure_summary(unit)
+        }
+        _ => {
+            Err(HttpError::NotFound)
         }
     }
 }
 
-fn rest_response_temperature_heater() -> Result<String, String>
+fn response_post(path: &str, body: &str, unit: TemperatureUnit) -> Result<ResponseBody, HttpError>
 {
-    let temperature = heater1_temperature();
-    let json = format!("\"heater_temp\":[{:.2}]", temperature);
-    log::info!("rest_response_temperature_heater: {}", json.as_str());
+    // "/calibration/{channel}/{point1|point2|offset|reset}" or "/calibration/vref"
+    if let Some(calibration_path) = path.strip_prefix("/calibration/") {
+        return rest_post_calibration(calibration_path, body, unit);
+    }
+    // "/sensors/{channel}/conversion"
+    if let Some(channel_name) = path.strip_prefix("/sensors/").and_then(|p| p.strip_suffix("/conversion")) {
+        return rest_post_sensors_conversion(channel_name, body);
+    }
+    // "/sensors/{channel}/profile"
+    if let Some(channel_name) = path.strip_prefix("/sensors/").and_then(|p| p.strip_suffix("/profile")) {
+        return rest_post_sensors_profile(channel_name, body);
+    }
+    // "/sensors/{channel}/filter"
+    if let Some(channel_name) = path.strip_prefix("/sensors/").and_then(|p| p.strip_suffix("/filter")) {
+        return rest_post_sensors_filter(channel_name, body, unit);
+    }
+
+    match path {
+        "/control/ramp" => {
+            rest_post_control_ramp(body, unit)
+        }
+        "/settings/unit" => {
+            rest_post_settings_unit(body)
+        }
+        "/stats/config" => {
+            rest_post_stats_config(body, unit)
+        }
+        "/cure/start" => {
+            rest_post_cure_start(unit)
+        }
+        "/energy/reset" => {
+            rest_post_energy_reset()
+        }
+        _ => {
+            Err(HttpError::NotFound)
+        }
+    }
+}
+
+fn rest_response_temperature_heater(unit: TemperatureUnit) -> Result<ResponseBody, HttpError>
+{
+    to_body(&HeaterTemperatureResponse {
+        heater_temp: [heater1_temperature().to_unit(unit)],
+        heater_unfiltered_temp: [heater1_unfiltered_temperature().to_unit(unit)],
+        heater_raw_temp: [heater1_raw_temperature().to_unit(unit)],
+        unit: unit.symbol(),
+    })
+}
 
-    Ok(json)
+fn rest_response_temperature_cpu(unit: TemperatureUnit) -> Result<ResponseBody, HttpError>
+{
+    to_body(&CpuTemperatureResponse {
+        cpu_temp: [cpu_temperature().to_unit(unit)],
+        unit: unit.symbol(),
+    })
 }
 
-fn rest_response_temperature_cpu() -> Result<String, String>
+fn rest_response_temperature_ambient(unit: TemperatureUnit) -> Result<ResponseBody, HttpError>
 {
-    let json = format!("\"cpu_temp\":[{:.2}]", cpu_temperature());
-    log::info!("rest_response_temperature_cpu(): {}", json.as_str());
+    to_body(&AmbientTemperatureResponse {
+        ambient_temp: ambient_temperature().map(|t| t.to_unit(unit)).into_iter().collect(),
+        ambient_humidity: ambient_humidity().into_iter().collect(),
+        unit: unit.symbol(),
+    })
+}
 
-    Ok(json)
+fn rest_response_temperature_all(unit: TemperatureUnit) -> Result<ResponseBody, HttpError>
+{
+    to_body(&TemperatureAllResponse {
+        heater_temp: [heater1_temperature().to_unit(unit)],
+        heater_unfiltered_temp: [heater1_unfiltered_temperature().to_unit(unit)],
+        heater_raw_temp: [heater1_raw_temperature().to_unit(unit)],
+        cpu_temp: [cpu_temperature().to_unit(unit)],
+        ambient_temp: ambient_temperature().map(|t| t.to_unit(unit)).into_iter().collect(),
+        ambient_humidity: ambient_humidity().into_iter().collect(),
+        unit: unit.symbol(),
+    })
 }
 
-fn rest_response_temperature_all() -> Result<String, String>
+fn rest_response_heater_power() -> Result<ResponseBody, HttpError>
 {
-    let heater_json = rest_response_temperature_heater()?;
-    let cpu_json = rest_response_temperature_cpu()?;
+    to_body(&HeaterPowerResponse {
+        heater_current: [heater1_current()],
+        heater_power: [heater1_power()],
+    })
+}
 
-    let json = format!("{},{}", heater_json, cpu_json);
-    log::info!("rest_response_temperature_all(): {}", json.as_str());
+fn rest_response_status(unit: TemperatureUnit) -> Result<ResponseBody, HttpError>
+{
+    to_body(&StatusResponse {
+        status: status_body(unit),
+        unit: unit.symbol(),
+    })
+}
 
-    Ok(json)
+fn rest_response_details(unit: TemperatureUnit) -> Result<ResponseBody, HttpError>
+{
+    to_body(&details_response(unit))
 }
 
-fn rest_response_status() -> Result<String, String>
+fn details_response(unit: TemperatureUnit) -> DetailsResponse
+{
+    DetailsResponse {
+        heater_temp: [heater1_temperature().to_unit(unit)],
+        heater_unfiltered_temp: [heater1_unfiltered_temperature().to_unit(unit)],
+        heater_raw_temp: [heater1_raw_temperature().to_unit(unit)],
+        cpu_temp: [cpu_temperature().to_unit(unit)],
+        ambient_temp: ambient_temperature().map(|t| t.to_unit(unit)).into_iter().collect(),
+        ambient_humidity: ambient_humidity().into_iter().collect(),
+        heater_current: [heater1_current()],
+        heater_power: [heater1_power()],
+        status: status_body(unit),
+        unit: unit.symbol(),
+    }
+}
+
+fn status_body(unit: TemperatureUnit) -> StatusBody
 {
     let (disp_errcode, disp_message) = match errcode() {
         ErrorCode::None => (0, String::from("")),
         ErrorCode::Heater1OverHeatError {errcode, message} => (errcode, message),
-        ErrorCode::Heater1ThermistorDisconnectError {errcode, message} => (errcode, message)
+        ErrorCode::Heater1ThermistorDisconnectError {errcode, message} => (errcode, message),
+        ErrorCode::Heater1OpenElementError {errcode, message} => (errcode, message),
+        ErrorCode::Heater1RelayWeldedError {errcode, message} => (errcode, message),
+        ErrorCode::Heater1OverCurrentError {errcode, message} => (errcode, message),
+        ErrorCode::EnclosureOverHeatError {errcode, message} => (errcode, message),
+    };
+    let ramp = ramp_setpoint();
+
+    StatusBody {
+        state: current_status_name(current_status()),
+        err_code: disp_errcode,
+        message: disp_message,
+        warnings: warnings(),
+        setpoint: ramp.setpoint().map(|sp| sp.to_unit(unit)),
+        target: ramp.target().to_unit(unit),
+        ramp_rate: unit.delta_from_celsius(ramp.rate_per_min()),
+    }
+}
+
+// Body: {"target":35.0,"ramp_rate":1.0}, in unit of request. ramp_rate is degrees per minute.
+fn rest_post_control_ramp(body: &str, unit: TemperatureUnit) -> Result<ResponseBody, HttpError>
+{
+    let request : RampRequest = parse_body(body)?;
+
+    let target = request.target.map(|t| Temperature::from_unit(t, unit));
+    let rate = request.ramp_rate.map(|r| unit.delta_to_celsius(r));
+    set_ramp(target, rate).map_err(HttpError::BadRequest)?;
+
+    let ramp = ramp_setpoint();
+    to_body(&RampResponse {
+        setpoint: ramp.setpoint().map(|sp| sp.to_unit(unit)),
+        target: ramp.target().to_unit(unit),
+        ramp_rate: unit.delta_from_celsius(ramp.rate_per_min()),
+        unit: unit.symbol(),
+    })
+}
+
+fn rest_response_energy() -> Result<ResponseBody, HttpError>
+{
+    let energy = heater1_energy();
+
+    to_body(&EnergyResponse {
+        energy: EnergyBody {
+            heater_wattage: HEATER1_WATTAGE,
+            session_on_time_s: energy.session_on_time_s(),
+            session_kwh: energy.session_kwh(),
+            session_cycles: energy.session_cycles(),
+            lifetime_on_time_s: energy.lifetime_on_time_s(),
+            lifetime_kwh: energy.lifetime_kwh(),
+            lifetime_cycles: energy.lifetime_cycles(),
+        },
+    })
+}
+
+fn rest_post_energy_reset() -> Result<ResponseBody, HttpError>
+{
+    // Session boundary is a good point to persist lifetime counters.
+    reset_heater1_energy_session();
+    save_energy().map_err(HttpError::Internal)?;
+
+    rest_response_energy()
+}
+
+fn rest_response_stats(unit: TemperatureUnit) -> Result<ResponseBody, HttpError>
+{
+    let config = stats_config();
+    let channel_stats = |channel: SensorChannel| ChannelStatsBody {
+        windows: (0..STATS_WINDOW_NUM).map(|i| stats_summary(&window_stats(channel, i), Some(config.window_s[i]), unit)).collect(),
+        cure: stats_summary(&session_stats(channel), None, unit),
+    };
+
+    to_body(&StatsResponse {
+        stats: StatsBody {
+            tolerance: unit.delta_from_celsius(config.tolerance.celsius()),
+            heater1: channel_stats(SensorChannel::Heater1),
+            cpu: channel_stats(SensorChannel::Cpu),
+        },
+        unit: unit.symbol(),
+    })
+}
+
+// Body: {"window1_s":60,"window2_s":600,"tolerance":0.5}, tolerance in unit of request. Omitted items keep current setting.
+fn rest_post_stats_config(body: &str, unit: TemperatureUnit) -> Result<ResponseBody, HttpError>
+{
+    let request : StatsConfigRequest = parse_body(body)?;
+
+    let mut config = stats_config();
+    for (i, window_s) in [request.window1_s, request.window2_s].iter().enumerate() {
+        if let Some(window_s) = window_s {
+            config.window_s[i] = *window_s;
+        }
+    }
+    if let Some(tolerance) = request.tolerance {
+        config.tolerance = Temperature::from_celsius(unit.delta_to_celsius(tolerance));
+    }
+    set_stats_config(config).map_err(HttpError::BadRequest)?;
+
+    rest_response_stats(unit)
+}
+
+fn rest_response_cure_summary(unit: TemperatureUnit) -> Result<ResponseBody, HttpError>
+{
+    let energy = heater1_energy();
+
+    to_body(&CureResponse {
+        cure: CureBody {
+            elapsed_s: cure_elapsed_s(),
+            on_time_s: energy.session_on_time_s(),
+            kwh: energy.session_kwh(),
+            cycles: energy.session_cycles(),
+            stats: per_channel(|ch| stats_summary(&session_stats(ch), None, unit)),
+        },
+        unit: unit.symbol(),
+    })
+}
+
+// Start new cure. Statistics and energy session restart together.
+fn rest_post_cure_start(unit: TemperatureUnit) -> Result<ResponseBody, HttpError>
+{
+    start_stats_session();
+    reset_heater1_energy_session();
+    save_energy().map_err(HttpError::Internal)?;
+
+    rest_response_cure_summary(unit)
+}
+
+fn stats_summary(stats: &StatsAccumulator, window_s: Option<u32>, unit: TemperatureUnit) -> StatsSummary
+{
+    StatsSummary {
+        window_s: window_s,
+        samples: stats.count(),
+        min: stats.min().map(|t| t.to_unit(unit)),
+        max: stats.max().map(|t| t.to_unit(unit)),
+        mean: stats.mean().map(|t| t.to_unit(unit)),
+        stddev: stats.stddev().map(|sd| unit.delta_from_celsius(sd)),
+        in_tolerance_s: stats.in_tolerance_s(THERMOMETER_TASK_TICK_MS),
+        in_tolerance_ratio: stats.in_tolerance_ratio(),
+    }
+}
+
+fn rest_response_calibration(unit: TemperatureUnit) -> Result<ResponseBody, HttpError>
+{
+    to_body(&CalibrationResponse {
+        calibration: CalibrationBody {
+            heater1: channel_calibration_body(SensorChannel::Heater1, unit),
+            cpu: channel_calibration_body(SensorChannel::Cpu, unit),
+            adc_vref: adc_vref(),
+        },
+        unit: unit.symbol(),
+    })
+}
+
+// "/calibration/vref" sets measured ADC reference voltage, others are "/calibration/{channel}/{operation}"
+// Body: {"reference":25.0}, reference temperature in unit of request.
+fn rest_post_calibration(calibration_path: &str, body: &str, unit: TemperatureUnit) -> Result<ResponseBody, HttpError>
+{
+    if calibration_path == "vref" {
+        return rest_post_calibration_vref(body, unit);
+    }
+    let (channel_name, operation) = calibration_path.split_once('/').unwrap_or((calibration_path, ""));
+    let channel = SensorChannel::from_name(channel_name)
+        .ok_or_else(|| HttpError::BadRequest(String::from("invalid calibration channel")))?;
+
+    let request : CalibrationRequest = parse_body(body)?;
+    // Calibration is done in Celsius.
+    let raw = raw_temperature(channel).celsius();
+    let reference = match request.reference.map(|r| Temperature::from_unit(r, unit)) {
+        Some(r) if !r.is_valid() => return Err(HttpError::BadRequest(String::from("reference is not a valid temperature"))),
+        r => r.map(|r| r.celsius()),
+    };
+
+    let result = match (operation, reference) {
+        ("point1", Some(reference)) => Ok(calibrate_point1(channel, raw, reference)),
+        ("point2", Some(reference)) => calibrate_point2(channel, raw, reference),
+        ("offset", Some(reference)) => calibrate_offset(channel, raw, reference),
+        ("point1", None) | ("point2", None) | ("offset", None) => Err(String::from("reference is required")),
+        ("reset", _) => Ok(reset_calibration(channel)),
+        _ => Err(String::from("invalid calibration operation")),
+    };
+    result.map_err(HttpError::BadRequest)?;
+    // Point1 is kept in RAM until point2. Request is valid here, so failure is flash write error.
+    if operation != "point1" {
+        save_calibration().map_err(HttpError::Internal)?;
+    }
+
+    rest_response_calibration(unit)
+}
+
+// Body: {"vref":3.28}
+fn rest_post_calibration_vref(body: &str, unit: TemperatureUnit) -> Result<ResponseBody, HttpError>
+{
+    let request : VrefRequest = parse_body(body)?;
+
+    request.vref.ok_or(String::from("vref is required"))
+        .and_then(set_adc_vref)
+        .map_err(HttpError::BadRequest)?;
+    save_adc_vref().map_err(HttpError::Internal)?;
+
+    rest_response_calibration(unit)
+}
+
+fn channel_calibration_body(channel: SensorChannel, unit: TemperatureUnit) -> ChannelCalibrationBody
+{
+    let calibration = calibration(channel);
+    let to_unit = |celsius: f32| Temperature::from_celsius(celsius).to_unit(unit);
+
+    ChannelCalibrationBody {
+        gain: calibration.gain(),
+        offset: calibration.offset(),
+        point1: calibration.point1().map(|(raw, reference)| CalibrationPoint { raw: to_unit(raw), reference: to_unit(reference) }),
+        raw_temp: raw_temperature(channel).to_unit(unit),
+        calibrated_temp: calibrated_temperature(channel).to_unit(unit),
+    }
+}
+
+fn rest_response_sensors_conversion() -> Result<ResponseBody, HttpError>
+{
+    // Only thermistor channels have selectable conversion.
+    to_body(&ConversionResponse {
+        conversion: ThermistorConversion {
+            heater1: channel_conversion_body(SensorChannel::Heater1),
+        },
+    })
+}
+
+// Body: {"mode":"table"}, {"mode":"beta","r25":10000.0,"beta":3380.0} or {"mode":"steinhart-hart","a":..,"b":..,"c":..}
+// Omitted formula parameters are taken from thermistor.toml.
+fn rest_post_sensors_conversion(channel_name: &str, body: &str) -> Result<ResponseBody, HttpError>
+{
+    let channel = SensorChannel::from_name(channel_name)
+        .ok_or_else(|| HttpError::BadRequest(String::from("invalid sensor channel")))?;
+    let request : ConversionRequest = parse_body(body)?;
+
+    parse_conversion(&request)
+        .and_then(|conversion| set_channel_conversion(channel, conversion))
+        .map_err(HttpError::BadRequest)?;
+    save_profiles().map_err(HttpError::Internal)?;
+
+    rest_response_sensors_conversion()
+}
+
+fn parse_conversion(request: &ConversionRequest) -> Result<Conversion, String>
+{
+    let mode = request.mode.ok_or(String::from("mode is required"))?;
+
+    let conversion = match (mode, default_formula()) {
+        ("table", _) => Ok(Conversion::Table),
+        ("beta", Conversion::Beta { r25, beta }) => Ok(Conversion::Beta {
+            r25: request.r25.unwrap_or(r25),
+            beta: request.beta.unwrap_or(beta),
+        }),
+        ("beta", _) => Ok(Conversion::Beta {
+            r25: request.r25.ok_or(String::from("r25 is required"))?,
+            beta: request.beta.ok_or(String::from("beta is required"))?,
+        }),
+        ("steinhart-hart", Conversion::SteinhartHart { a, b, c }) => Ok(Conversion::SteinhartHart {
+            a: request.a.unwrap_or(a),
+            b: request.b.unwrap_or(b),
+            c: request.c.unwrap_or(c),
+        }),
+        ("steinhart-hart", _) => Ok(Conversion::SteinhartHart {
+            a: request.a.ok_or(String::from("a is required"))?,
+            b: request.b.ok_or(String::from("b is required"))?,
+            c: request.c.ok_or(String::from("c is required"))?,
+        }),
+        _ => Err(String::from("invalid mode")),
+    }?;
+
+    conversion.validate()?;
+    Ok(conversion)
+}
+
+fn channel_conversion_body(channel: SensorChannel) -> ConversionBody
+{
+    let mut body = conversion_body(&conversion(channel));
+    body.profile = Some(profile_name(channel));
+    body
+}
+
+fn conversion_body(conversion: &Conversion) -> ConversionBody
+{
+    let mut body = ConversionBody { name: None, profile: None, mode: conversion.name(), r25: None, beta: None, a: None, b: None, c: None };
+    match *conversion {
+        Conversion::Table => {}
+        Conversion::Beta { r25, beta } => {
+            body.r25 = Some(r25);
+            body.beta = Some(beta);
+        }
+        Conversion::SteinhartHart { a, b, c } => {
+            body.a = Some(a);
+            body.b = Some(b);
+            body.c = Some(c);
+        }
+    }
+    body
+}
+
+fn rest_response_sensors_profiles() -> Result<ResponseBody, HttpError>
+{
+    to_body(&ProfilesResponse {
+        profiles: thermistor_profiles().iter().map(|profile| {
+            let mut body = conversion_body(&profile.conversion);
+            body.name = Some(profile.name);
+            body
+        }).collect(),
+    })
+}
+
+// Body: {"profile":"100k-b3950"}. Custom parameters are set by "/sensors/{channel}/conversion".
+fn rest_post_sensors_profile(channel_name: &str, body: &str) -> Result<ResponseBody, HttpError>
+{
+    let channel = SensorChannel::from_name(channel_name)
+        .ok_or_else(|| HttpError::BadRequest(String::from("invalid sensor channel")))?;
+    let request : ProfileRequest = parse_body(body)?;
+
+    request.profile.ok_or(String::from("profile is required"))
+        .and_then(|name| select_profile(channel, name))
+        .map_err(HttpError::BadRequest)?;
+    save_profiles().map_err(HttpError::Internal)?;
+
+    rest_response_sensors_conversion()
+}
+
+fn rest_response_sensors_filter(unit: TemperatureUnit) -> Result<ResponseBody, HttpError>
+{
+    to_body(&FilterResponse {
+        filter: per_channel(|ch| filter_body(ch, unit)),
+        unit: unit.symbol(),
+    })
+}
+
+// Body: {"oversampling":64,"median":5,"smoothing":"ema","alpha":0.22,"kalman":true,"kalman_q":0.0001,"kalman_r":0.01}
+// smoothing is "none", "ema" (with alpha) or "moving_average" (with window). Omitted items keep current setting.
+fn rest_post_sensors_filter(channel_name: &str, body: &str, unit: TemperatureUnit) -> Result<ResponseBody, HttpError>
+{
+    let channel = SensorChannel::from_name(channel_name)
+        .ok_or_else(|| HttpError::BadRequest(String::from("invalid sensor channel")))?;
+    let request : FilterRequest = parse_body(body)?;
+
+    parse_filter_config(filter_config(channel), &request)
+        .and_then(|config| set_filter_config(channel, config).map_err(String::from))
+        .map_err(HttpError::BadRequest)?;
+
+    rest_response_sensors_filter(unit)
+}
+
+fn parse_filter_config(current: FilterConfig, request: &FilterRequest) -> Result<FilterConfig, String>
+{
+    let mut config = current;
+
+    if let Some(n) = request.oversampling {
+        config.oversampling = n;
+    }
+    if let Some(n) = request.median {
+        config.median_window = n;
+    }
+    config.smoothing = match (request.smoothing, current.smoothing) {
+        (None, smoothing) => smoothing,
+        (Some("none"), _) => Smoothing::None,
+        (Some("ema"), Smoothing::Ema { alpha }) => Smoothing::Ema { alpha: request.alpha.unwrap_or(alpha) },
+        (Some("ema"), _) => Smoothing::Ema { alpha: request.alpha.ok_or(String::from("alpha is required"))? },
+        (Some("moving_average"), Smoothing::MovingAverage { window }) => Smoothing::MovingAverage {
+            window: request.window.unwrap_or(window)
+        },
+        (Some("moving_average"), _) => Smoothing::MovingAverage {
+            window: request.window.ok_or(String::from("window is required"))?
+        },
+        (Some(_), _) => return Err(String::from("invalid smoothing")),
+    };
+    config.kalman = match (request.kalman, current.kalman) {
+        (Some(false), _) => None,
+        (Some(true), _) | (None, Some(_)) => {
+            let default = current.kalman.unwrap_or(KalmanConfig { process_noise: 0.0001, measurement_noise: 0.01 });
+            Some(KalmanConfig {
+                process_noise: request.kalman_q.unwrap_or(default.process_noise),
+                measurement_noise: request.kalman_r.unwrap_or(default.measurement_noise),
+            })
+        }
+        (None, None) => None,
+    };
+
+    Ok(config)
+}
+
+fn filter_body(channel: SensorChannel, unit: TemperatureUnit) -> FilterBody
+{
+    let config = filter_config(channel);
+    let (smoothing, alpha, window) = match config.smoothing {
+        Smoothing::None => ("none", None, None),
+        Smoothing::Ema { alpha } => ("ema", Some(alpha), None),
+        Smoothing::MovingAverage { window } => ("moving_average", None, Some(window)),
+    };
+
+    FilterBody {
+        oversampling: config.oversampling,
+        median: config.median_window,
+        smoothing: smoothing,
+        alpha: alpha,
+        window: window,
+        kalman: config.kalman.is_some(),
+        kalman_q: config.kalman.map(|k| k.process_noise),
+        kalman_r: config.kalman.map(|k| k.measurement_noise),
+        unfiltered_temp: unfiltered_temperature(channel).to_unit(unit),
+        filtered_temp: calibrated_temperature(channel).to_unit(unit),
+    }
+}
+
+fn rest_response_sensors_raw(unit: TemperatureUnit) -> Result<ResponseBody, HttpError>
+{
+    // Order of ADC_SAMPLED_NAMES.
+    to_body(&SensorsRawResponse {
+        sensors_raw: RawChannels {
+            heater1: raw_body(0, unit),
+            heater1_current: raw_body(1, unit),
+            cpu: raw_body(2, unit),
+        },
+        unit: unit.symbol(),
+    })
+}
+
+// Raw ADC statistics over last second, and value converted from latest raw ADC before/after filtering.
+fn raw_body(index: usize, unit: TemperatureUnit) -> RawBody
+{
+    let summary = raw_summary(index);
+    let mut body = RawBody {
+        adc: summary.latest,
+        samples: summary.count,
+        adc_min: summary.min,
+        adc_max: summary.max,
+        adc_mean: summary.mean,
+        adc_stddev: summary.stddev,
+        saturated_low: summary.saturated_low,
+        saturated_high: summary.saturated_high,
+        conversion: None,
+        table_index: None,
+        table_alpha: None,
+        unfiltered_current: None,
+        filtered_current: None,
+        adc_vref: None,
+        unfiltered_temp: None,
+        filtered_temp: None,
+    };
+
+    match (index, summary.latest) {
+        (0, Some(level)) => {
+            let conversion = conversion(SensorChannel::Heater1);
+            if let Conversion::Table = conversion {
+                let (table_index, alpha) = table_position(level);
+                body.table_index = Some(table_index);
+                body.table_alpha = Some(alpha);
+            }
+            body.conversion = Some(conversion.name());
+            body.unfiltered_temp = Some(heater1_unfiltered_temperature().to_unit(unit));
+            body.filtered_temp = Some(heater1_temperature().to_unit(unit));
+        }
+        (1, Some(level)) => {
+            body.unfiltered_current = Some(convert_to_ampere(level));
+            body.filtered_current = Some(heater1_current());
+        }
+        (2, Some(_)) => {
+            body.adc_vref = Some(adc_vref());
+            body.unfiltered_temp = Some(cpu_unfiltered_temperature().to_unit(unit));
+            body.filtered_temp = Some(cpu_temperature().to_unit(unit));
+        }
+        _ => {}
+    }
+
+    body
+}
+
+// Query: from=<seconds since boot>&resolution=<1s|1m>
+// Returns at most HISTORY_PAGE_MAX records, "next" is "from" of next page or null if no more records.
+fn rest_response_history(query: &str, unit: TemperatureUnit) -> Result<ResponseBody, HttpError>
+{
+    let from = match query_value(query, "from").map(|v| v.parse::<u32>()) {
+        None => 0,
+        Some(Ok(from)) => from,
+        Some(Err(_)) => return Err(HttpError::BadRequest(String::from("invalid from"))),
+    };
+    // Full page may have more records after it. Next page starts right after last record.
+    let next = |last: Option<u32>, len: usize, interval: u32| match last {
+        Some(t) if len == HISTORY_PAGE_MAX => Some(t + interval),
+        _ => None,
+    };
+
+    match query_value(query, "resolution").unwrap_or("1s") {
+        "1s" | "1" => {
+            let samples = history_fine(from);
+            let records : Vec<HistoryFineRecord> = samples.iter().map(|(t, s)| {
+                (*t, s.heater().to_unit(unit), s.cpu().to_unit(unit), s.setpoint().map(|sp| sp.to_unit(unit)))
+            }).collect();
+            log::info!("rest_response_history(): {} records", records.len());
+
+            to_body(&HistoryResponse {
+                history: HistoryBody {
+                    resolution: HISTORY_FINE_INTERVAL_S,
+                    now: Instant::now().as_secs(),
+                    next: next(samples.last().map(|(t, _)| *t), records.len(), HISTORY_FINE_INTERVAL_S),
+                    fields: &["t", "heater", "cpu", "setpoint"],
+                    records: records,
+                },
+                unit: unit.symbol(),
+            })
+        }
+        "1m" | "60" => {
+            let buckets = history_coarse(from);
+            let records : Vec<HistoryCoarseRecord> = buckets.iter().map(|(t, b)| {
+                (*t, b.heater_avg().to_unit(unit), b.heater_min().to_unit(unit), b.heater_max().to_unit(unit), b.cpu_avg().to_unit(unit), b.setpoint_avg().map(|sp| sp.to_unit(unit)))
+            }).collect();
+            log::info!("rest_response_history(): {} records", records.len());
+
+            to_body(&HistoryResponse {
+                history: HistoryBody {
+                    resolution: HISTORY_COARSE_INTERVAL_S,
+                    now: Instant::now().as_secs(),
+                    next: next(buckets.last().map(|(t, _)| *t), records.len(), HISTORY_COARSE_INTERVAL_S),
+                    fields: &["t", "heater_avg", "heater_min", "heater_max", "cpu_avg", "setpoint_avg"],
+                    records: records,
+                },
+                unit: unit.symbol(),
+            })
+        }
+        _ => Err(HttpError::BadRequest(String::from("invalid resolution"))),
+    }
+}
+
+fn rest_response_settings_unit() -> Result<ResponseBody, HttpError>
+{
+    to_body(&UnitResponse { unit: default_unit().symbol() })
+}
+
+// Body: {"unit":"F"}. "C", "F" or "K", used when request has no unit query parameter.
+fn rest_post_settings_unit(body: &str) -> Result<ResponseBody, HttpError>
+{
+    let request : UnitRequest = parse_body(body)?;
+
+    let unit = request.unit.ok_or(String::from("unit is required"))
+        .and_then(|name| TemperatureUnit::from_name(name).ok_or(String::from("invalid unit")))
+        .map_err(HttpError::BadRequest)?;
+    // Unit is valid here, so failure is flash write error.
+    set_default_unit(unit).map_err(HttpError::Internal)?;
+
+    rest_response_settings_unit()
+}
+
+fn rest_response_diagnostics() -> Result<ResponseBody, HttpError>
+{
+    to_body(&DiagnosticsResponse {
+        diagnostics: DiagnosticsBody {
+            controller: loop_timing_body(TimedLoop::Controller),
+            thermometer: loop_timing_body(TimedLoop::Thermometer),
+            plausibility: per_channel(plausibility_body),
+            rest: RestServerBody {
+                workers: REST_WORKER_NUM,
+                active_connections: active_connections(),
+                streams: active_streams(),
+            },
+        },
+    })
+}
+
+// Temperatures are always in Celsius, base unit of Prometheus. Invalid temperature is NaN.
+fn rest_response_metrics() -> Result<ResponseBody, HttpError>
+{
+    let mut metrics = MetricsBody::new();
+    let ramp = ramp_setpoint();
+    let energy = heater1_energy();
+
+    metrics.family("reginheater_temperature_celsius", "gauge", "Filtered temperature of sensor channel.");
+    metrics.value("reginheater_temperature_celsius", "channel=\"heater1\"", metric_celsius(heater1_temperature()));
+    metrics.value("reginheater_temperature_celsius", "channel=\"cpu\"", metric_celsius(cpu_temperature()));
+    // Ambient sensor is optional.
+    if let Some(temperature) = ambient_temperature() {
+        metrics.value("reginheater_temperature_celsius", "channel=\"ambient\"", metric_celsius(temperature));
+    }
+    if let Some(humidity) = ambient_humidity() {
+        metrics.family("reginheater_ambient_humidity_percent", "gauge", "Relative humidity of ambient sensor.");
+        metrics.value("reginheater_ambient_humidity_percent", "", humidity);
+    }
+
+    // Setpoint is null until controller starts ramp.
+    if let Some(setpoint) = ramp.setpoint() {
+        metrics.family("reginheater_setpoint_celsius", "gauge", "Current setpoint of ramp.");
+        metrics.value("reginheater_setpoint_celsius", "", metric_celsius(setpoint));
+    }
+    metrics.family("reginheater_target_celsius", "gauge", "Target temperature of ramp.");
+    metrics.value("reginheater_target_celsius", "", metric_celsius(ramp.target()));
+
+    metrics.family("reginheater_heater_on", "gauge", "1 if heater output is on.");
+    metrics.value("reginheater_heater_on", "", heater_port_is_on() as u8);
+    metrics.family("reginheater_heater_current_amperes", "gauge", "Measured heater current.");
+    metrics.value("reginheater_heater_current_amperes", "", heater1_current());
+    metrics.family("reginheater_heater_power_watts", "gauge", "Heater power from measured current.");
+    metrics.value("reginheater_heater_power_watts", "", heater1_power());
+    // Duty is rate() of on time.
+    metrics.family("reginheater_heater_on_seconds_total", "counter", "Lifetime heater on time.");
+    metrics.value("reginheater_heater_on_seconds_total", "", energy.lifetime_on_time_s());
+    metrics.family("reginheater_heater_cycles_total", "counter", "Lifetime heater on cycles.");
+    metrics.value("reginheater_heater_cycles_total", "", energy.lifetime_cycles());
+
+    let state = current_status_name(current_status());
+    metrics.family("reginheater_controller_state", "gauge", "1 for current controller state.");
+    for name in CONTROLLER_STATE_NAMES.iter() {
+        metrics.value("reginheater_controller_state", &format!("state=\"{}\"", name), (*name == state) as u8);
+    }
+    metrics.family("reginheater_faults_total", "counter", "Faults detected since boot.");
+    for (name, count) in FAULT_NAMES.iter().zip(fault_counts().iter()) {
+        metrics.value("reginheater_faults_total", &format!("fault=\"{}\"", name), count);
+    }
+
+    metrics.family("reginheater_uptime_seconds", "counter", "Time since boot.");
+    metrics.value("reginheater_uptime_seconds", "", Instant::now().as_secs());
+    metrics.family("reginheater_heap_used_bytes", "gauge", "Used heap memory.");
+    metrics.value("reginheater_heap_used_bytes", "", crate::HEAP.used());
+    metrics.family("reginheater_heap_free_bytes", "gauge", "Free heap memory.");
+    metrics.value("reginheater_heap_free_bytes", "", crate::HEAP.free());
+    // RSSI is read after first scrape, so value is one scrape old.
+    #[cfg(feature = "wifi-rssi")]
+    {
+        request_wifi_rssi();
+        if let Some(rssi) = wifi_rssi() {
+            metrics.family("reginheater_wifi_rssi_dbm", "gauge", "Wi-Fi signal strength.");
+            metrics.value("reginheater_wifi_rssi_dbm", "", rssi);
+        }
+    }
+
+    metrics.family("reginheater_http_requests_total", "counter", "HTTP requests by response status code.");
+    for (code, count) in HTTP_STATUS_CODES.iter().zip(http_requests().iter()) {
+        metrics.value("reginheater_http_requests_total", &format!("code=\"{}\"", code), count);
+    }
+    metrics.family("reginheater_http_connections", "gauge", "Connections being served.");
+    metrics.value("reginheater_http_connections", "", active_connections());
+    metrics.family("reginheater_http_streams", "gauge", "Event stream and WebSocket clients.");
+    metrics.value("reginheater_http_streams", "", active_streams());
+
+    Ok(metrics.finish())
+}
+
+// Invalid temperature has no Celsius value.
+fn metric_celsius(temperature: Temperature) -> f32
+{
+    if temperature.is_valid() { temperature.celsius() } else { f32::NAN }
+}
+
+impl MetricsBody
+{
+    fn new() -> Self
+    {
+        Self { body: ResponseBody::new(), family: String::new(), dropped: 0 }
+    }
+
+    // HELP and TYPE lines of metric. Previous family is moved to body.
+    fn family(&mut self, name: &str, kind: &str, help: &str)
+    {
+        self.flush();
+        // Writing to String never fails.
+        let _ = write!(self.family, "# HELP {} {}\n# TYPE {} {}\n", name, help, name, kind);
+    }
+
+    fn value(&mut self, name: &str, labels: &str, value: impl core::fmt::Display)
+    {
+        if labels.is_empty() {
+            let _ = writeln!(self.family, "{} {}", name, value);
+        }
+        else {
+            let _ = writeln!(self.family, "{}{{{}}} {}", name, labels, value);
+        }
+    }
+
+    // Family that does not fit is dropped whole, so scraper never sees half of it.
+    fn flush(&mut self)
+    {
+        if self.body.len() + self.family.len() <= RESPONSE_BODY_SIZE - METRICS_RESERVED_SIZE {
+            let _ = self.body.extend_from_slice(self.family.as_bytes());
+        }
+        else {
+            self.dropped += 1;
+        }
+        self.family.clear();
+    }
+
+    fn finish(mut self) -> ResponseBody
+    {
+        self.flush();
+        // Space for this family is reserved by flush().
+        self.family("reginheater_metrics_dropped_families", "gauge", "Metric families dropped because response is full.");
+        let dropped = self.dropped;
+        self.value("reginheater_metrics_dropped_families", "", dropped);
+        let _ = self.body.extend_from_slice(self.family.as_bytes());
+        self.body
+    }
+}
+
+fn loop_timing_body(timed_loop: TimedLoop) -> LoopTimingBody
+{
+    let timing = loop_timing(timed_loop);
+
+    LoopTimingBody {
+        period_ms: timing.period_ms(),
+        ticks: timing.ticks(),
+        avg_period_us: timing.avg_period_us(),
+        min_period_us: timing.min_period_us(),
+        max_period_us: timing.max_period_us(),
+        max_latency_us: timing.max_latency_us(),
+        overruns: timing.overruns(),
+        warning: timing.warning(),
+    }
+}
+
+fn plausibility_body(channel: SensorChannel) -> PlausibilityBody
+{
+    let status = plausibility(channel);
+
+    PlausibilityBody {
+        rejected: status.rejected,
+        resynced: status.resynced,
+        noisy: status.noisy,
+    }
+}
+
+fn warnings() -> Vec<&'static str>
+{
+    let mut warnings : Vec<&'static str> = Vec::new();
+    if loop_timing_warning() {
+        warnings.push("loop_timing");
+    }
+    if sensor_noisy_warning() {
+        warnings.push("sensor_noisy");
+    }
+    if CURRENT_SENSE_FITTED && heater1_current_fault().is_some() {
+        warnings.push("current_sense_fault");
+    }
+
+    warnings
+}
+
+fn per_channel<T>(f: impl Fn(SensorChannel) -> T) -> PerChannel<T>
+{
+    PerChannel {
+        heater1: f(SensorChannel::Heater1),
+        cpu: f(SensorChannel::Cpu),
+    }
+}
+
+// Reply to frame received by WebSocket.
+fn websocket_action(frame: &FrameHeader, payload: &[u8], unit: TemperatureUnit) -> WebSocketAction
+{
+    // Commands are small, fragmented message is not supported.
+    if !frame.fin {
+        return WebSocketAction::Fail(CLOSE_UNSUPPORTED_DATA, String::from("fragmented message is not supported"));
+    }
+
+    match frame.opcode {
+        Opcode::Text => match from_utf8(payload) {
+            Ok(text) => WebSocketAction::Send(Opcode::Text, websocket_command(text, unit).to_vec()),
+            Err(_) => WebSocketAction::Fail(CLOSE_INVALID_PAYLOAD, String::from("text is not UTF-8")),
+        },
+        Opcode::Ping => WebSocketAction::Send(Opcode::Pong, payload.to_vec()),
+        Opcode::Pong => WebSocketAction::None,
+        // Echo back status code of client.
+        Opcode::Close => match parse_close_payload(payload) {
+            Ok((code, _)) => WebSocketAction::Close(code.unwrap_or(CLOSE_NORMAL)),
+            Err(e) => WebSocketAction::Fail(CLOSE_PROTOCOL_ERROR, e),
+        },
+        Opcode::Binary | Opcode::Continuation => {
+            WebSocketAction::Fail(CLOSE_UNSUPPORTED_DATA, String::from("only text message is supported"))
+        }
+    }
+}
+
+// Text message is command, e.g. {"command":"setpoint","target":35.0}. Returns reply message.
+fn websocket_command(text: &str, unit: TemperatureUnit) -> ResponseBody
+{
+    let (command, id, result) = match parse_body::<WebSocketCommand>(text) {
+        Ok(request) => (request.command.unwrap_or(""), request.id, run_websocket_command(&request, unit)),
+        Err(e) => ("", None, Err(e.message())),
     };
+    log::info!("WebSocket command \"{}\": {}", command, if result.is_ok() { "ok" } else { "failed" });
 
-    let json = format!("\"status\":{{\"state\":\"{}\",\"err_code\":{},\"message\":\"{}\"}}", 
-        current_status_string(current_status()),
-        disp_errcode,
-        disp_message
-    );
-    log::info!("rest_response_status(): {}", json.as_str());
+    // Reply is small, it always fits in buffer.
+    to_body(&WebSocketEvent {
+        event: "reply",
+        data: WebSocketReply { command: command, id: id, ok: result.is_ok(), message: result.err() },
+    }).unwrap_or_default()
+}
 
-    Ok(json) 
+fn run_websocket_command(request: &WebSocketCommand, unit: TemperatureUnit) -> Result<(), String>
+{
+    match request.command {
+        Some("start") => start_heater(),
+        Some("stop") => stop_heater(),
+        Some("ack") => acknowledge_error(),
+        Some("setpoint") => {
+            let target = request.target.map(|t| Temperature::from_unit(t, unit));
+            let rate = request.ramp_rate.map(|r| unit.delta_to_celsius(r));
+            set_ramp(target, rate).map(|_| ())
+        }
+        Some(command) => Err(format!("unknown command {}", command)),
+        None => Err(String::from("command is required")),
+    }
 }
 
-fn rest_response_details() -> Result<String, String>
+impl TelemetrySchedule
 {
-    let temp_json = rest_response_temperature_all()?;
-    let status_json = rest_response_status()?;
+    fn new(telemetry: Telemetry) -> Self
+    {
+        Self { telemetry: telemetry, last_status: None, next_sample: Instant::now() }
+    }
+
+    // Status is sent as soon as state, fault or warnings changed. Setpoint changes every moment while ramping, so ignore it.
+    // Temperature is sent every interval.
+    fn poll(&mut self) -> Vec<TelemetryEvent>
+    {
+        let mut events : Vec<TelemetryEvent> = Vec::new();
+        let unit = self.telemetry.unit;
 
-    let json = format!("{},{}", temp_json, status_json);
-    log::info!("rest_response_details(): {}", json.as_str());
+        let status = status_body(unit);
+        let key = (status.state, status.err_code, status.warnings.clone());
+        if self.last_status.as_ref() != Some(&key) {
+            events.push(TelemetryEvent::Status(StatusResponse { status: status, unit: unit.symbol() }));
+            self.last_status = Some(key);
+        }
+        if Instant::now() >= self.next_sample {
+            events.push(TelemetryEvent::Temperature(details_response(unit)));
+            self.next_sample = Instant::now() + self.telemetry.interval;
+        }
 
-    Ok(json) 
+        events
+    }
 }
 
 fn get_tcp_state_string(state: embassy_net::tcp::State) -> String
@@ -256,14 +1852,38 @@ fn get_tcp_state_string(state: embassy_net::tcp::State) -> String
     }
 }
 
-fn current_status_string(state: State) -> String
+fn current_status_name(state: State) -> &'static str
 {
     match state {
-        State::Initializing => String::from("Initializing"),
-        State::Heating => String::from("Heating"),
-        State::Saturating => String::from("Saturating"),
-        State::Error => String::from("Error"),
+        State::Initializing => "Initializing",
+        State::Heating => "Heating",
+        State::Saturating => "Saturating",
+        State::Stopped => "Stopped",
+        State::Error => "Error",
     }
 }
 
+// Value of "key=value" in URL query string.
+fn query_value<'a>(query: &'a str, key: &str) -> Option<&'a str>
+{
+    query.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, v)| v)
+}
+
+// Serialize response into fixed size buffer.
+fn to_body<T: Serialize>(response: &T) -> Result<ResponseBody, HttpError>
+{
+    serde_json_core::to_vec(response).map_err(|_| HttpError::Internal(String::from("response too large")))
+}
 
+// Parse JSON request body. Empty body is same as {}. Escaped characters in strings are not supported.
+fn parse_body<'a, T: Deserialize<'a>>(body: &'a str) -> Result<T, HttpError>
+{
+    let body = if body.trim().is_empty() { "{}" } else { body };
+
+    serde_json_core::from_str(body)
+        .map(|(request, _)| request)
+        .map_err(|e| HttpError::BadRequest(format!("invalid request body: {:?}", e)))
+}
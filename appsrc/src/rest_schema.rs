@@ -0,0 +1,518 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use serde::{Serialize, Deserialize};
+
+// JSON schema of REST API.
+// Temperatures are in unit of request, and "unit" of response tells it.
+
+//
+// Responses
+//
+
+#[derive(Serialize)]
+pub struct ErrorResponse<'a>
+{
+    pub error : ErrorBody<'a>,
+}
+
+#[derive(Serialize)]
+pub struct ErrorBody<'a>
+{
+    pub status : u16,
+    pub message : &'a str,
+}
+
+// Value for each sensor channel, keyed by channel name.
+#[derive(Serialize)]
+pub struct PerChannel<T>
+{
+    pub heater1 : T,
+    pub cpu : T,
+}
+
+#[derive(Serialize)]
+pub struct HeaterTemperatureResponse
+{
+    pub heater_temp : [f32; 1],
+    pub heater_unfiltered_temp : [f32; 1],
+    pub heater_raw_temp : [f32; 1],
+    pub unit : &'static str,
+}
+
+#[derive(Serialize)]
+pub struct CpuTemperatureResponse
+{
+    pub cpu_temp : [f32; 1],
+    pub unit : &'static str,
+}
+
+// Arrays are empty if ambient sensor is not connected.
+#[derive(Serialize)]
+pub struct AmbientTemperatureResponse
+{
+    pub ambient_temp : Vec<f32>,
+    pub ambient_humidity : Vec<f32>,
+    pub unit : &'static str,
+}
+
+#[derive(Serialize)]
+pub struct TemperatureAllResponse
+{
+    pub heater_temp : [f32; 1],
+    pub heater_unfiltered_temp : [f32; 1],
+    pub heater_raw_temp : [f32; 1],
+    pub cpu_temp : [f32; 1],
+    pub ambient_temp : Vec<f32>,
+    pub ambient_humidity : Vec<f32>,
+    pub unit : &'static str,
+}
+
+#[derive(Serialize)]
+pub struct HeaterPowerResponse
+{
+    pub heater_current : [f32; 1],
+    pub heater_power : [f32; 1],
+}
+
+#[derive(Serialize)]
+pub struct StatusBody
+{
+    pub state : &'static str,
+    pub err_code : u32,
+    pub message : String,
+    pub warnings : Vec<&'static str>,
+    // null until controller starts ramp.
+    pub setpoint : Option<f32>,
+    pub target : f32,
+    pub ramp_rate : f32,
+}
+
+#[derive(Serialize)]
+pub struct StatusResponse
+{
+    pub status : StatusBody,
+    pub unit : &'static str,
+}
+
+// Everything monitor_tool shows in one response.
+#[derive(Serialize)]
+pub struct DetailsResponse
+{
+    pub heater_temp : [f32; 1],
+    pub heater_unfiltered_temp : [f32; 1],
+    pub heater_raw_temp : [f32; 1],
+    pub cpu_temp : [f32; 1],
+    pub ambient_temp : Vec<f32>,
+    pub ambient_humidity : Vec<f32>,
+    pub heater_current : [f32; 1],
+    pub heater_power : [f32; 1],
+    pub status : StatusBody,
+    pub unit : &'static str,
+}
+
+#[derive(Serialize)]
+pub struct RampResponse
+{
+    pub setpoint : Option<f32>,
+    pub target : f32,
+    pub ramp_rate : f32,
+    pub unit : &'static str,
+}
+
+#[derive(Serialize)]
+pub struct EnergyBody
+{
+    pub heater_wattage : f32,
+    pub session_on_time_s : u64,
+    pub session_kwh : f32,
+    pub session_cycles : u32,
+    pub lifetime_on_time_s : u64,
+    pub lifetime_kwh : f32,
+    pub lifetime_cycles : u32,
+}
+
+#[derive(Serialize)]
+pub struct EnergyResponse
+{
+    pub energy : EnergyBody,
+}
+
+// window_s is given only for rolling windows.
+#[derive(Serialize)]
+pub struct StatsSummary
+{
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window_s : Option<u32>,
+    pub samples : u32,
+    pub min : Option<f32>,
+    pub max : Option<f32>,
+    pub mean : Option<f32>,
+    pub stddev : Option<f32>,
+    pub in_tolerance_s : f32,
+    pub in_tolerance_ratio : Option<f32>,
+}
+
+#[derive(Serialize)]
+pub struct ChannelStatsBody
+{
+    pub windows : Vec<StatsSummary>,
+    pub cure : StatsSummary,
+}
+
+#[derive(Serialize)]
+pub struct StatsBody
+{
+    pub tolerance : f32,
+    pub heater1 : ChannelStatsBody,
+    pub cpu : ChannelStatsBody,
+}
+
+#[derive(Serialize)]
+pub struct StatsResponse
+{
+    pub stats : StatsBody,
+    pub unit : &'static str,
+}
+
+#[derive(Serialize)]
+pub struct CureBody
+{
+    pub elapsed_s : u64,
+    pub on_time_s : u64,
+    pub kwh : f32,
+    pub cycles : u32,
+    pub stats : PerChannel<StatsSummary>,
+}
+
+#[derive(Serialize)]
+pub struct CureResponse
+{
+    pub cure : CureBody,
+    pub unit : &'static str,
+}
+
+#[derive(Serialize)]
+pub struct CalibrationPoint
+{
+    pub raw : f32,
+    pub reference : f32,
+}
+
+// Temperatures are in unit of request. gain and offset are in Celsius, calibration is done in Celsius.
+#[derive(Serialize)]
+pub struct ChannelCalibrationBody
+{
+    pub gain : f32,
+    pub offset : f32,
+    pub point1 : Option<CalibrationPoint>,
+    pub raw_temp : f32,
+    pub calibrated_temp : f32,
+}
+
+#[derive(Serialize)]
+pub struct CalibrationBody
+{
+    pub heater1 : ChannelCalibrationBody,
+    pub cpu : ChannelCalibrationBody,
+    pub adc_vref : f32,
+}
+
+#[derive(Serialize)]
+pub struct CalibrationResponse
+{
+    pub calibration : CalibrationBody,
+    pub unit : &'static str,
+}
+
+// Conversion of channel (with "profile"), or built-in profile (with "name").
+// Formula parameters are given for "beta" and "steinhart-hart" mode.
+#[derive(Serialize)]
+pub struct ConversionBody
+{
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name : Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile : Option<&'static str>,
+    pub mode : &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r25 : Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub beta : Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub a : Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub b : Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub c : Option<f32>,
+}
+
+// Conversion is selectable on thermistor channels only.
+#[derive(Serialize)]
+pub struct ThermistorConversion
+{
+    pub heater1 : ConversionBody,
+}
+
+#[derive(Serialize)]
+pub struct ConversionResponse
+{
+    pub conversion : ThermistorConversion,
+}
+
+#[derive(Serialize)]
+pub struct ProfilesResponse
+{
+    pub profiles : Vec<ConversionBody>,
+}
+
+#[derive(Serialize)]
+pub struct FilterBody
+{
+    pub oversampling : usize,
+    pub median : usize,
+    pub smoothing : &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alpha : Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window : Option<usize>,
+    pub kalman : bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kalman_q : Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kalman_r : Option<f32>,
+    pub unfiltered_temp : f32,
+    pub filtered_temp : f32,
+}
+
+#[derive(Serialize)]
+pub struct FilterResponse
+{
+    pub filter : PerChannel<FilterBody>,
+    pub unit : &'static str,
+}
+
+// Raw ADC statistics over last second, and value converted from latest raw ADC before/after filtering.
+// Converted values depend on input, and are omitted until first sample.
+#[derive(Serialize)]
+pub struct RawBody
+{
+    pub adc : Option<u16>,
+    pub samples : u32,
+    pub adc_min : u16,
+    pub adc_max : u16,
+    pub adc_mean : f32,
+    pub adc_stddev : f32,
+    pub saturated_low : bool,
+    pub saturated_high : bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conversion : Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub table_index : Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub table_alpha : Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unfiltered_current : Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filtered_current : Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub adc_vref : Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unfiltered_temp : Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filtered_temp : Option<f32>,
+}
+
+// Keyed by ADC_SAMPLED_NAMES.
+#[derive(Serialize)]
+pub struct RawChannels
+{
+    pub heater1 : RawBody,
+    pub heater1_current : RawBody,
+    pub cpu : RawBody,
+}
+
+#[derive(Serialize)]
+pub struct SensorsRawResponse
+{
+    pub sensors_raw : RawChannels,
+    pub unit : &'static str,
+}
+
+// Records are arrays of values named by "fields". "next" is "from" of next page, or null if no more records.
+#[derive(Serialize)]
+pub struct HistoryBody<R>
+{
+    pub resolution : u32,
+    pub now : u64,
+    pub next : Option<u32>,
+    pub fields : &'static [&'static str],
+    pub records : Vec<R>,
+}
+
+#[derive(Serialize)]
+pub struct HistoryResponse<R>
+{
+    pub history : HistoryBody<R>,
+    pub unit : &'static str,
+}
+
+// [t, heater, cpu, setpoint]
+pub type HistoryFineRecord = (u32, f32, f32, Option<f32>);
+// [t, heater_avg, heater_min, heater_max, cpu_avg, setpoint_avg]
+pub type HistoryCoarseRecord = (u32, f32, f32, f32, f32, Option<f32>);
+
+#[derive(Serialize)]
+pub struct UnitResponse
+{
+    pub unit : &'static str,
+}
+
+#[derive(Serialize)]
+pub struct LoopTimingBody
+{
+    pub period_ms : u64,
+    pub ticks : u32,
+    pub avg_period_us : u64,
+    pub min_period_us : u64,
+    pub max_period_us : u64,
+    pub max_latency_us : u64,
+    pub overruns : u32,
+    pub warning : bool,
+}
+
+#[derive(Serialize)]
+pub struct PlausibilityBody
+{
+    pub rejected : u32,
+    pub resynced : u32,
+    pub noisy : bool,
+}
+
+#[derive(Serialize)]
+pub struct RestServerBody
+{
+    pub workers : usize,
+    pub active_connections : usize,
+    pub streams : usize,
+}
+
+#[derive(Serialize)]
+pub struct DiagnosticsBody
+{
+    pub controller : LoopTimingBody,
+    pub thermometer : LoopTimingBody,
+    pub plausibility : PerChannel<PlausibilityBody>,
+    pub rest : RestServerBody,
+}
+
+#[derive(Serialize)]
+pub struct DiagnosticsResponse
+{
+    pub diagnostics : DiagnosticsBody,
+}
+
+// Text message sent by WebSocket. "data" is same as REST response, e.g. "temperature" has body of GET /details.
+#[derive(Serialize)]
+pub struct WebSocketEvent<T>
+{
+    pub event : &'static str,
+    pub data : T,
+}
+
+// "id" of command is echoed back, message tells why command failed.
+#[derive(Serialize)]
+pub struct WebSocketReply<'a>
+{
+    pub command : &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id : Option<u32>,
+    pub ok : bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message : Option<String>,
+}
+
+//
+// Request bodies. Omitted items are None.
+//
+
+// Temperatures in unit of request, ramp_rate is degrees per minute.
+#[derive(Deserialize)]
+pub struct RampRequest
+{
+    pub target : Option<f32>,
+    pub ramp_rate : Option<f32>,
+}
+
+// tolerance in unit of request.
+#[derive(Deserialize)]
+pub struct StatsConfigRequest
+{
+    pub window1_s : Option<u32>,
+    pub window2_s : Option<u32>,
+    pub tolerance : Option<f32>,
+}
+
+// Reference temperature in Celsius.
+#[derive(Deserialize)]
+pub struct CalibrationRequest
+{
+    pub reference : Option<f32>,
+}
+
+#[derive(Deserialize)]
+pub struct VrefRequest
+{
+    pub vref : Option<f32>,
+}
+
+#[derive(Deserialize)]
+pub struct ConversionRequest<'a>
+{
+    #[serde(borrow)]
+    pub mode : Option<&'a str>,
+    pub r25 : Option<f32>,
+    pub beta : Option<f32>,
+    pub a : Option<f32>,
+    pub b : Option<f32>,
+    pub c : Option<f32>,
+}
+
+#[derive(Deserialize)]
+pub struct ProfileRequest<'a>
+{
+    #[serde(borrow)]
+    pub profile : Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+pub struct FilterRequest<'a>
+{
+    pub oversampling : Option<usize>,
+    pub median : Option<usize>,
+    #[serde(borrow)]
+    pub smoothing : Option<&'a str>,
+    pub alpha : Option<f32>,
+    pub window : Option<usize>,
+    pub kalman : Option<bool>,
+    pub kalman_q : Option<f32>,
+    pub kalman_r : Option<f32>,
+}
+
+#[derive(Deserialize)]
+pub struct UnitRequest<'a>
+{
+    #[serde(borrow)]
+    pub unit : Option<&'a str>,
+}
+
+// Command sent by WebSocket, "start", "stop", "ack" or "setpoint".
+// "setpoint" takes target and ramp_rate same as RampRequest.
+#[derive(Deserialize)]
+pub struct WebSocketCommand<'a>
+{
+    #[serde(borrow)]
+    pub command : Option<&'a str>,
+    pub id : Option<u32>,
+    pub target : Option<f32>,
+    pub ramp_rate : Option<f32>,
+}
@@ -0,0 +1,35 @@
+use crate::temperature::*;
+
+// Fault reported by temperature sensor. Reading is not valid while sensor has fault.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SensorFault
+{
+    // Probe wire is open (thermocouple open circuit, thermistor disconnected).
+    Open,
+    ShortToGnd,
+    ShortToVcc,
+    // Communication with sensor chip failed.
+    Bus,
+}
+
+impl SensorFault
+{
+    pub fn name(&self) -> &'static str
+    {
+        match self {
+            SensorFault::Open => "open",
+            SensorFault::ShortToGnd => "short_to_gnd",
+            SensorFault::ShortToVcc => "short_to_vcc",
+            SensorFault::Bus => "bus",
+        }
+    }
+}
+
+// Temperature sensor backend.
+pub trait TemperatureSensor
+{
+    // Sensor name for logs and API, e.g. "heater1".
+    fn identity(&self) -> &'static str;
+
+    async fn read(&mut self) -> Result<Temperature, SensorFault>;
+}
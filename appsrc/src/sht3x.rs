@@ -0,0 +1,196 @@
+use embedded_hal_async::i2c::I2c;
+
+//
+// static const variables
+//
+
+// SHT3x (SHT30/SHT31/SHT35) temperature/humidity sensor
+pub const SHT3X_DEFAULT_ADDRESS : u8 = 0x44;
+// Single shot measurement, high repeatability, clock stretching disabled
+const SHT3X_CMD_MEASURE_HIGH_REPEATABILITY : [u8; 2] = [0x24, 0x00];
+// Wait between start_measurement() and read_measurement().
+pub const SHT3X_MEASUREMENT_TIME_MS : u64 = 16;
+const SHT3X_CRC_POLYNOMIAL : u8 = 0x31;
+const SHT3X_CRC_INIT : u8 = 0xFF;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct AmbientReading
+{
+    pub temperature : f32,
+    pub humidity : f32,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AmbientError<E>
+{
+    Bus(E),
+    Crc,
+}
+
+pub struct Sht3x<I>
+{
+    i2c: I,
+    address: u8,
+}
+
+impl<I> Sht3x<I>
+    where I: I2c
+{
+    pub fn new(i2c: I, address: u8) -> Self {
+        Self {
+            i2c: i2c,
+            address: address,
+        }
+    }
+
+    // Sensor NACKs the command if it is not connected.
+    pub async fn start_measurement(&mut self) -> Result<(), AmbientError<I::Error>>
+    {
+        self.i2c.write(self.address, &SHT3X_CMD_MEASURE_HIGH_REPEATABILITY).await.map_err(AmbientError::Bus)
+    }
+
+    pub async fn read_measurement(&mut self) -> Result<AmbientReading, AmbientError<I::Error>>
+    {
+        let mut buf = [0u8; 6];
+        self.i2c.read(self.address, &mut buf).await.map_err(AmbientError::Bus)?;
+
+        decode_measurement(&buf)
+    }
+}
+
+pub fn decode_measurement<E>(buf: &[u8; 6]) -> Result<AmbientReading, AmbientError<E>>
+{
+    // [temp MSB, temp LSB, temp CRC, humidity MSB, humidity LSB, humidity CRC]
+    if crc8(&buf[0..2]) != buf[2] || crc8(&buf[3..5]) != buf[5] {
+        return Err(AmbientError::Crc);
+    }
+
+    let raw_temp = u16::from_be_bytes([buf[0], buf[1]]) as f32;
+    let raw_humidity = u16::from_be_bytes([buf[3], buf[4]]) as f32;
+
+    // According to chapter 4.13 Conversion of Signal Output in SHT3x datasheet
+    Ok(AmbientReading {
+        temperature: -45.0 + 175.0 * raw_temp / 65535.0,
+        humidity: 100.0 * raw_humidity / 65535.0,
+    })
+}
+
+pub fn crc8(data: &[u8]) -> u8
+{
+    let mut crc = SHT3X_CRC_INIT;
+
+    for byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if (crc & 0x80) != 0 { (crc << 1) ^ SHT3X_CRC_POLYNOMIAL } else { crc << 1 };
+        }
+    }
+
+    crc
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use core::future::Future;
+    use core::pin::pin;
+    use core::task::{Context, Poll, Waker};
+    use embedded_hal_async::i2c::{ErrorKind, ErrorType, NoAcknowledgeSource, Operation};
+
+    // I2C bus with one SHT3x. None emulates missing sensor, every transfer is NACKed.
+    struct MockBus
+    {
+        response : Option<[u8; 6]>,
+        written : Vec<(u8, Vec<u8>)>,
+    }
+
+    impl ErrorType for MockBus
+    {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for MockBus
+    {
+        async fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), ErrorKind>
+        {
+            let response = self.response.ok_or(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address))?;
+            for operation in operations {
+                match operation {
+                    Operation::Write(data) => self.written.push((address, data.to_vec())),
+                    Operation::Read(buf) => buf.copy_from_slice(&response[..buf.len()]),
+                }
+            }
+            Ok(())
+        }
+    }
+
+    // Mock bus never pends.
+    fn block_on<F: Future>(future: F) -> F::Output
+    {
+        let mut future = pin!(future);
+        match future.as_mut().poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("mock bus pended"),
+        }
+    }
+
+    fn frame(raw_temp: u16, raw_humidity: u16) -> [u8; 6]
+    {
+        let [t0, t1] = raw_temp.to_be_bytes();
+        let [h0, h1] = raw_humidity.to_be_bytes();
+        [t0, t1, crc8(&[t0, t1]), h0, h1, crc8(&[h0, h1])]
+    }
+
+    #[test]
+    fn crc8_datasheet_example()
+    {
+        // Chapter 4.12 Checksum Calculation in SHT3x datasheet
+        assert_eq!(crc8(&[0xBE, 0xEF]), 0x92);
+    }
+
+    #[test]
+    fn decode_good_frame()
+    {
+        let reading = decode_measurement::<()>(&frame(0x6666, 0x8000)).unwrap();
+        assert!((reading.temperature - 25.0).abs() < 0.01);
+        assert!((reading.humidity - 50.0).abs() < 0.01);
+
+        let low = decode_measurement::<()>(&frame(0, 0)).unwrap();
+        assert_eq!(low, AmbientReading { temperature: -45.0, humidity: 0.0 });
+        let high = decode_measurement::<()>(&frame(0xFFFF, 0xFFFF)).unwrap();
+        assert_eq!(high, AmbientReading { temperature: 130.0, humidity: 100.0 });
+    }
+
+    #[test]
+    fn decode_bad_crc()
+    {
+        let mut buf = frame(0x6666, 0x8000);
+        buf[2] ^= 0x01;
+        assert_eq!(decode_measurement::<()>(&buf), Err(AmbientError::Crc));
+
+        let mut buf = frame(0x6666, 0x8000);
+        buf[4] ^= 0x01;
+        assert_eq!(decode_measurement::<()>(&buf), Err(AmbientError::Crc));
+    }
+
+    #[test]
+    fn measure_connected_sensor()
+    {
+        let mut sensor = Sht3x::new(MockBus { response: Some(frame(0x6666, 0x8000)), written: Vec::new() }, SHT3X_DEFAULT_ADDRESS);
+
+        assert_eq!(block_on(sensor.start_measurement()), Ok(()));
+        assert!((block_on(sensor.read_measurement()).unwrap().temperature - 25.0).abs() < 0.01);
+        assert_eq!(sensor.i2c.written, vec![(SHT3X_DEFAULT_ADDRESS, SHT3X_CMD_MEASURE_HIGH_REPEATABILITY.to_vec())]);
+    }
+
+    #[test]
+    fn missing_sensor_is_bus_error()
+    {
+        let mut sensor = Sht3x::new(MockBus { response: None, written: Vec::new() }, SHT3X_DEFAULT_ADDRESS);
+
+        let nack = ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address);
+        assert_eq!(block_on(sensor.start_measurement()), Err(AmbientError::Bus(nack)));
+        assert_eq!(block_on(sensor.read_measurement()), Err(AmbientError::Bus(nack)));
+    }
+}
@@ -0,0 +1,315 @@
+use core::cell::RefCell;
+use num_traits::float::Float;
+
+use alloc::string::String;
+use embassy_time::Instant;
+use embassy_sync::blocking_mutex::{Mutex, raw::ThreadModeRawMutex};
+
+use crate::thermometer::*;
+use crate::controller::*;
+use crate::temperature::*;
+
+//
+// static const variables
+//
+pub const STATS_WINDOW_NUM : usize = 2;
+// Each rolling window is kept as ring of buckets, bucket length = window length / bucket number.
+const STATS_WINDOW_BUCKETS : usize = 60;
+const STATS_WINDOW_DEFAULT_S : [u32; STATS_WINDOW_NUM] = [60, 600];
+const STATS_WINDOW_MIN_S : u32 = STATS_WINDOW_BUCKETS as u32;
+const STATS_WINDOW_MAX_S : u32 = 24 * 60 * 60;
+const STATS_TOLERANCE_DEFAULT : Temperature = Temperature::from_centi(50);
+
+// Running min/max/mean/variance (Welford), and count of samples within tolerance of setpoint.
+#[derive(Copy, Clone)]
+pub struct StatsAccumulator
+{
+    count : u32,
+    mean : f64,
+    m2 : f64,
+    min : Temperature,
+    max : Temperature,
+    // Samples taken while setpoint exists, and those within tolerance.
+    with_setpoint : u32,
+    in_tolerance : u32,
+}
+
+struct StatsWindow
+{
+    buckets : [StatsAccumulator; STATS_WINDOW_BUCKETS],
+    pos : usize,
+    current : StatsAccumulator,
+    current_s : u32,
+    bucket_s : u32,
+}
+
+struct ChannelStats
+{
+    second : StatsAccumulator,
+    windows : [StatsWindow; STATS_WINDOW_NUM],
+    session : StatsAccumulator,
+}
+
+#[derive(Copy, Clone)]
+pub struct StatsConfig
+{
+    pub window_s : [u32; STATS_WINDOW_NUM],
+    pub tolerance : Temperature,
+}
+
+//
+// static variables
+//
+static STATS : Mutex<ThreadModeRawMutex, RefCell<[ChannelStats; SENSOR_CHANNEL_NUM]>> = Mutex::new(RefCell::new([ChannelStats::new(), ChannelStats::new()]));
+static STATS_CONFIG : Mutex<ThreadModeRawMutex, RefCell<StatsConfig>> = Mutex::new(RefCell::new(StatsConfig::new()));
+static CURE_START_MS : Mutex<ThreadModeRawMutex, RefCell<u64>> = Mutex::new(RefCell::new(0));
+
+impl StatsAccumulator
+{
+    pub const fn new() -> Self
+    {
+        Self {
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+            min: Temperature::from_centi(i32::MAX),
+            max: Temperature::from_centi(i32::MIN),
+            with_setpoint: 0,
+            in_tolerance: 0,
+        }
+    }
+
+    fn add(&mut self, temperature: Temperature, setpoint: Option<Temperature>, tolerance: Temperature)
+    {
+        let value = temperature.celsius() as f64;
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+        self.min = self.min.min(temperature);
+        self.max = self.max.max(temperature);
+
+        if let Some(sp) = setpoint {
+            self.with_setpoint += 1;
+            if temperature >= sp - tolerance && temperature <= sp + tolerance {
+                self.in_tolerance += 1;
+            }
+        }
+    }
+
+    // Combine two accumulators (Chan et al. parallel variance).
+    fn merge(&mut self, other: &StatsAccumulator)
+    {
+        if other.count == 0 {
+            return;
+        }
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        self.mean += delta * other.count as f64 / count as f64;
+        self.m2 += other.m2 + delta * delta * self.count as f64 * other.count as f64 / count as f64;
+        self.count = count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.with_setpoint += other.with_setpoint;
+        self.in_tolerance += other.in_tolerance;
+    }
+
+    pub fn count(&self) -> u32 { self.count }
+    pub fn min(&self) -> Option<Temperature> { if self.count > 0 { Some(self.min) } else { None } }
+    pub fn max(&self) -> Option<Temperature> { if self.count > 0 { Some(self.max) } else { None } }
+    pub fn mean(&self) -> Option<Temperature> { if self.count > 0 { Some(Temperature::from_celsius(self.mean as f32)) } else { None } }
+
+    // Population standard deviation[celsius].
+    pub fn stddev(&self) -> Option<f32>
+    {
+        if self.count == 0 {
+            return None;
+        }
+        Some((self.m2 / self.count as f64).max(0.0).sqrt() as f32)
+    }
+
+    // Time within tolerance of setpoint, from number of samples taken every sample_interval_ms.
+    pub fn in_tolerance_s(&self, sample_interval_ms: u64) -> f32
+    {
+        (self.in_tolerance as u64 * sample_interval_ms) as f32 / 1000.0
+    }
+
+    // Ratio of samples within tolerance, None if there was no setpoint.
+    pub fn in_tolerance_ratio(&self) -> Option<f32>
+    {
+        if self.with_setpoint == 0 {
+            return None;
+        }
+        Some(self.in_tolerance as f32 / self.with_setpoint as f32)
+    }
+}
+
+impl StatsWindow
+{
+    const fn new(window_s: u32) -> Self
+    {
+        Self {
+            buckets: [StatsAccumulator::new(); STATS_WINDOW_BUCKETS],
+            pos: 0,
+            current: StatsAccumulator::new(),
+            current_s: 0,
+            bucket_s: window_s / STATS_WINDOW_BUCKETS as u32,
+        }
+    }
+
+    fn push_second(&mut self, second: &StatsAccumulator)
+    {
+        self.current.merge(second);
+        self.current_s += 1;
+        if self.current_s >= self.bucket_s {
+            self.buckets[self.pos] = self.current;
+            self.pos = (self.pos + 1) % STATS_WINDOW_BUCKETS;
+            self.current = StatsAccumulator::new();
+            self.current_s = 0;
+        }
+    }
+
+    // Covers last window length, plus samples of bucket being filled.
+    fn summary(&self) -> StatsAccumulator
+    {
+        let mut summary = self.current;
+        for bucket in self.buckets.iter() {
+            summary.merge(bucket);
+        }
+        summary
+    }
+}
+
+impl ChannelStats
+{
+    const fn new() -> Self
+    {
+        Self {
+            second: StatsAccumulator::new(),
+            windows: [StatsWindow::new(STATS_WINDOW_DEFAULT_S[0]), StatsWindow::new(STATS_WINDOW_DEFAULT_S[1])],
+            session: StatsAccumulator::new(),
+        }
+    }
+
+    fn record(&mut self, temperature: Temperature, setpoint: Option<Temperature>, config: &StatsConfig, samples_per_second: u32)
+    {
+        self.second.add(temperature, setpoint, config.tolerance);
+
+        // Windows are updated every second, to keep bucket arithmetic out of every sample.
+        if self.second.count >= samples_per_second {
+            for window in self.windows.iter_mut() {
+                window.push_second(&self.second);
+            }
+            self.session.merge(&self.second);
+            self.second = StatsAccumulator::new();
+        }
+    }
+}
+
+impl StatsConfig
+{
+    pub const fn new() -> Self
+    {
+        Self {
+            window_s: STATS_WINDOW_DEFAULT_S,
+            tolerance: STATS_TOLERANCE_DEFAULT,
+        }
+    }
+}
+
+// Feed one accepted sample of channel. Called by thermometer_task every sample_interval_ms.
+pub fn record_stats(channel: SensorChannel, temperature: Temperature, sample_interval_ms: u64)
+{
+    // Broken reading would dominate mean and deviation.
+    if !temperature.is_valid() {
+        return;
+    }
+
+    // Only heater is controlled to setpoint.
+    let setpoint = match channel {
+        SensorChannel::Heater1 => ramp_setpoint().setpoint(),
+        _ => None,
+    };
+    let config = stats_config();
+    let samples_per_second = (1000 / sample_interval_ms).max(1) as u32;
+
+    STATS.lock(|lock| {
+        lock.borrow_mut()[channel as usize].record(temperature, setpoint, &config, samples_per_second);
+    });
+}
+
+pub fn window_stats(channel: SensorChannel, window: usize) -> StatsAccumulator
+{
+    STATS.lock(|lock| {
+        lock.borrow()[channel as usize].windows[window].summary()
+    })
+}
+
+// Statistics since cure start.
+pub fn session_stats(channel: SensorChannel) -> StatsAccumulator
+{
+    STATS.lock(|lock| {
+        let stats = lock.borrow();
+        let mut session = stats[channel as usize].session;
+        session.merge(&stats[channel as usize].second);
+        session
+    })
+}
+
+// Start new cure. Session statistics restart from now.
+pub fn start_stats_session()
+{
+    STATS.lock(|lock| {
+        for stats in lock.borrow_mut().iter_mut() {
+            stats.session = StatsAccumulator::new();
+        }
+    });
+    CURE_START_MS.lock(|lock| {
+        *lock.borrow_mut() = Instant::now().as_millis()
+    });
+}
+
+// Elapsed seconds since cure start (since boot until first cure is started).
+pub fn cure_elapsed_s() -> u64
+{
+    let start_ms = CURE_START_MS.lock(|lock| {
+        *(lock.borrow_mut())
+    });
+    (Instant::now().as_millis() - start_ms) / 1000
+}
+
+pub fn stats_config() -> StatsConfig
+{
+    STATS_CONFIG.lock(|lock| {
+        *(lock.borrow_mut())
+    })
+}
+
+// Changing window length restarts the window.
+pub fn set_stats_config(config: StatsConfig) -> Result<StatsConfig, String>
+{
+    for window_s in config.window_s.iter() {
+        if *window_s < STATS_WINDOW_MIN_S || *window_s > STATS_WINDOW_MAX_S || *window_s % STATS_WINDOW_BUCKETS as u32 != 0 {
+            return Err(format!("window must be multiple of {} in [{}, {}] seconds", STATS_WINDOW_BUCKETS, STATS_WINDOW_MIN_S, STATS_WINDOW_MAX_S));
+        }
+    }
+    if config.tolerance.centi() <= 0 {
+        return Err(String::from("tolerance must be positive"));
+    }
+
+    let current = stats_config();
+    STATS.lock(|lock| {
+        for stats in lock.borrow_mut().iter_mut() {
+            for (i, window) in stats.windows.iter_mut().enumerate() {
+                if config.window_s[i] != current.window_s[i] {
+                    *window = StatsWindow::new(config.window_s[i]);
+                }
+            }
+        }
+    });
+    STATS_CONFIG.lock(|lock| {
+        *lock.borrow_mut() = config
+    });
+
+    Ok(config)
+}
@@ -0,0 +1,113 @@
+use core::cell::RefCell;
+use core::ops::DerefMut;
+
+use alloc::string::String;
+
+use embassy_rp::flash::{Flash, ERASE_SIZE};
+use embassy_rp::peripherals::FLASH;
+use embassy_sync::blocking_mutex::{Mutex, raw::ThreadModeRawMutex};
+
+//
+// static const variables
+//
+
+// Pico W has 2MiB flash. Program area is limited to first 1MiB by memory.x,
+// so persistent storage is placed after program area. One erase sector per slot.
+pub const FLASH_SIZE : usize = 2 * 1024 * 1024;
+const STORAGE_OFFSET : u32 = 0x10_0000;
+const STORAGE_MAGIC : u32 = 0x5247_4854;   // "RGHT"
+const STORAGE_HEADER_SIZE : usize = 12;
+pub const STORAGE_PAYLOAD_MAX : usize = 256;
+
+// Persistent data slot. Do not renumber, slot number is flash sector position.
+#[derive(Copy, Clone)]
+pub enum StorageSlot
+{
+    Energy = 0,
+    Calibration = 1,
+    AdcReference = 2,
+    ThermistorProfile = 3,
+    TemperatureUnit = 4,
+}
+
+//
+// static variables
+//
+static STORAGE_FLASH : Mutex<ThreadModeRawMutex, RefCell<Option<Flash<'static, FLASH, FLASH_SIZE>>>> = Mutex::new(RefCell::new(None));
+
+pub fn set_storage_flash(flash: Flash<'static, FLASH, FLASH_SIZE>)
+{
+    STORAGE_FLASH.lock(|lock| {
+        *(lock.borrow_mut()) = Some(flash);
+    })
+}
+
+// Read payload of slot into buf, and return payload length.
+pub fn storage_read(slot: StorageSlot, buf: &mut [u8]) -> Result<usize, String>
+{
+    let offset = slot_offset(slot);
+    let mut record = [0u8; STORAGE_HEADER_SIZE + STORAGE_PAYLOAD_MAX];
+
+    STORAGE_FLASH.lock(|lock| {
+        match lock.borrow_mut().deref_mut() {
+            Some(flash) => flash.read(offset, &mut record).map_err(|e| format!("Flash read error: {:?}", e)),
+            None => Err(String::from("Flash storage is not initialized.")),
+        }
+    })?;
+
+    // Record : [magic(4)][payload length(4)][checksum(4)][payload]
+    let magic = u32::from_le_bytes([record[0], record[1], record[2], record[3]]);
+    let len = u32::from_le_bytes([record[4], record[5], record[6], record[7]]) as usize;
+    let checksum = u32::from_le_bytes([record[8], record[9], record[10], record[11]]);
+
+    if magic != STORAGE_MAGIC {
+        return Err(String::from("Storage slot is empty."));
+    }
+    if len > STORAGE_PAYLOAD_MAX || len > buf.len() {
+        return Err(format!("Storage slot length is invalid: {}", len));
+    }
+    let payload = &record[STORAGE_HEADER_SIZE..STORAGE_HEADER_SIZE + len];
+    if calc_checksum(payload) != checksum {
+        return Err(String::from("Storage slot checksum mismatch."));
+    }
+
+    buf[..len].copy_from_slice(payload);
+    Ok(len)
+}
+
+pub fn storage_write(slot: StorageSlot, data: &[u8]) -> Result<(), String>
+{
+    if data.len() > STORAGE_PAYLOAD_MAX {
+        return Err(format!("Storage payload too large: {}", data.len()));
+    }
+
+    let offset = slot_offset(slot);
+    let mut record = [0xFFu8; STORAGE_HEADER_SIZE + STORAGE_PAYLOAD_MAX];
+    record[0..4].copy_from_slice(&STORAGE_MAGIC.to_le_bytes());
+    record[4..8].copy_from_slice(&(data.len() as u32).to_le_bytes());
+    record[8..12].copy_from_slice(&calc_checksum(data).to_le_bytes());
+    record[STORAGE_HEADER_SIZE..STORAGE_HEADER_SIZE + data.len()].copy_from_slice(data);
+
+    STORAGE_FLASH.lock(|lock| {
+        match lock.borrow_mut().deref_mut() {
+            Some(flash) => {
+                flash.erase(offset, offset + ERASE_SIZE as u32).map_err(|e| format!("Flash erase error: {:?}", e))?;
+                flash.write(offset, &record).map_err(|e| format!("Flash write error: {:?}", e))
+            }
+            None => Err(String::from("Flash storage is not initialized.")),
+        }
+    })
+}
+
+fn slot_offset(slot: StorageSlot) -> u32
+{
+    STORAGE_OFFSET + (slot as u32) * ERASE_SIZE as u32
+}
+
+fn calc_checksum(data: &[u8]) -> u32
+{
+    // FNV-1a 32bit
+    data.iter().fold(0x811C_9DC5u32, |hash, b| {
+        (hash ^ *b as u32).wrapping_mul(0x0100_0193)
+    })
+}
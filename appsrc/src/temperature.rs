@@ -0,0 +1,170 @@
+use core::cell::RefCell;
+use core::ops::{Add, Sub};
+use num_traits::float::FloatCore;
+
+use alloc::string::String;
+use embassy_sync::blocking_mutex::{Mutex, raw::ThreadModeRawMutex};
+
+use crate::storage::*;
+
+// Temperature in fixed-point centi-degrees Celsius. 1000 = 10.00[Celsius], same as TEMPERATURE_TABLE.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct Temperature(i32);
+
+// Unit of temperature in API responses and setpoint inputs.
+#[derive(Copy, Clone, PartialEq)]
+pub enum TemperatureUnit
+{
+    Celsius = 0,
+    Fahrenheit = 1,
+    Kelvin = 2,
+}
+
+//
+// static variables
+//
+static DEFAULT_UNIT : Mutex<ThreadModeRawMutex, RefCell<TemperatureUnit>> = Mutex::new(RefCell::new(TemperatureUnit::Celsius));
+
+impl Temperature
+{
+    // Reading from NaN or infinity. Far below ERROR_CTH_DISCONNECT_THRESHOLD, so it is detected as disconnected sensor.
+    pub const INVALID : Temperature = Temperature(i32::MIN);
+
+    pub const fn from_centi(centi: i32) -> Self
+    {
+        Self(centi)
+    }
+
+    pub fn from_celsius(celsius: f32) -> Self
+    {
+        if !celsius.is_finite() {
+            return Self::INVALID;
+        }
+        Self((celsius * 100.0).round() as i32)
+    }
+
+    pub fn is_valid(&self) -> bool
+    {
+        *self != Self::INVALID
+    }
+
+    pub fn from_unit(value: f32, unit: TemperatureUnit) -> Self
+    {
+        let celsius = match unit {
+            TemperatureUnit::Celsius => value,
+            TemperatureUnit::Fahrenheit => (value - 32.0) / 1.8,
+            TemperatureUnit::Kelvin => value - 273.15,
+        };
+        Self::from_celsius(celsius)
+    }
+
+    pub const fn centi(&self) -> i32
+    {
+        self.0
+    }
+
+    pub fn celsius(&self) -> f32
+    {
+        self.0 as f32 / 100.0
+    }
+
+    pub fn to_unit(&self, unit: TemperatureUnit) -> f32
+    {
+        match unit {
+            TemperatureUnit::Celsius => self.celsius(),
+            TemperatureUnit::Fahrenheit => self.celsius() * 1.8 + 32.0,
+            TemperatureUnit::Kelvin => self.celsius() + 273.15,
+        }
+    }
+}
+
+impl Add for Temperature
+{
+    type Output = Temperature;
+
+    fn add(self, rhs: Temperature) -> Temperature
+    {
+        // Saturate, INVALID must not wrap around to high temperature.
+        Temperature(self.0.saturating_add(rhs.0))
+    }
+}
+
+impl Sub for Temperature
+{
+    type Output = Temperature;
+
+    fn sub(self, rhs: Temperature) -> Temperature
+    {
+        Temperature(self.0.saturating_sub(rhs.0))
+    }
+}
+
+impl TemperatureUnit
+{
+    pub fn from_name(name: &str) -> Option<Self>
+    {
+        match name {
+            "C" | "c" | "celsius" => Some(TemperatureUnit::Celsius),
+            "F" | "f" | "fahrenheit" => Some(TemperatureUnit::Fahrenheit),
+            "K" | "k" | "kelvin" => Some(TemperatureUnit::Kelvin),
+            _ => None,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str
+    {
+        match self {
+            TemperatureUnit::Celsius => "C",
+            TemperatureUnit::Fahrenheit => "F",
+            TemperatureUnit::Kelvin => "K",
+        }
+    }
+
+    // Temperature difference (e.g. ramp rate) in Celsius -> this unit.
+    pub fn delta_from_celsius(&self, delta: f32) -> f32
+    {
+        match self {
+            TemperatureUnit::Fahrenheit => delta * 1.8,
+            _ => delta,
+        }
+    }
+
+    // Temperature difference in this unit -> Celsius.
+    pub fn delta_to_celsius(&self, delta: f32) -> f32
+    {
+        match self {
+            TemperatureUnit::Fahrenheit => delta / 1.8,
+            _ => delta,
+        }
+    }
+}
+
+// Unit used when request has no "unit" query parameter.
+pub fn default_unit() -> TemperatureUnit
+{
+    DEFAULT_UNIT.lock(|lock| {
+        *(lock.borrow_mut())
+    })
+}
+
+pub fn set_default_unit(unit: TemperatureUnit) -> Result<(), String>
+{
+    DEFAULT_UNIT.lock(|lock| {
+        *lock.borrow_mut() = unit
+    });
+
+    storage_write(StorageSlot::TemperatureUnit, &[unit as u8])
+}
+
+pub fn load_default_unit()
+{
+    let mut buf = [0u8; 1];
+    let unit = match storage_read(StorageSlot::TemperatureUnit, &mut buf) {
+        Ok(1) if buf[0] == TemperatureUnit::Fahrenheit as u8 => TemperatureUnit::Fahrenheit,
+        Ok(1) if buf[0] == TemperatureUnit::Kelvin as u8 => TemperatureUnit::Kelvin,
+        _ => TemperatureUnit::Celsius,
+    };
+    DEFAULT_UNIT.lock(|lock| {
+        *lock.borrow_mut() = unit
+    });
+}
@@ -4,561 +4,603 @@ use num_traits::float::FloatCore;
 use embassy_time::{Duration, Ticker};
 use embassy_rp::gpio::{Pin};
 use embassy_rp::adc::{Adc};
-use embassy_rp::peripherals::{PIN_26, PIN_27};
+use embassy_rp::peripherals::{DMA_CH1, PIN_26, PIN_27, PIN_28};
 
 use embassy_sync::blocking_mutex::{Mutex, raw::ThreadModeRawMutex};
 
+use crate::current::*;
+use crate::diagnostics::*;
+use crate::calibration::*;
+use crate::ntc::*;
+use crate::filter::*;
+use crate::adc_dma::*;
+use crate::temperature::*;
+use crate::stats::*;
+use crate::sensor::*;
 
-pub struct ADCIo<'a, T1: Pin, T2: Pin>
+// Pins are owned to reserve them as ADC inputs, sampling is done by AdcDma.
+#[allow(dead_code)]
+pub struct ADCIo<'a, T1: Pin, T2: Pin, T3: Pin>
 {
-    adc: Adc<'a>,
+    adc_dma: AdcDma<'a>,
     heater1 : T1,
     heater2 : T2,
+    heater1_current : T3,
 }
 
-impl<'a, T1, T2> ADCIo<'a, T1, T2>
-    where T1: Pin, T2: Pin   
+impl<'a, T1, T2, T3> ADCIo<'a, T1, T2, T3>
+    where T1: Pin, T2: Pin, T3: Pin
 {
-    pub fn new(adc_in: Adc<'a>, h1: T1, h2: T2 ) -> Self {
+    pub fn new(adc_in: Adc<'a>, dma: DMA_CH1, h1: T1, h2: T2, c1: T3 ) -> Self {
         Self { 
-            adc: adc_in,
+            adc_dma: AdcDma::new(adc_in, dma),
             heater1: h1,
             heater2: h2,
+            heater1_current: c1,
+        }
+    }
+}
+
+pub const THERMOMETER_TASK_TICK_MS : u64 = 20;
+// Plausibility check. Bath temperature can not change faster than this, larger step is connector noise.
+const PLAUSIBLE_RATE_CELCIUS_PER_S : f32 = 5.0;
+// Allowed step regardless of elapsed time, covers measurement noise.
+const PLAUSIBLE_STEP_MARGIN_CELCIUS : f32 = 1.0;
+// Persisting reading is accepted as real change, so sensor faults are not hidden from error detection.
+const PLAUSIBILITY_RESYNC_TICKS : u32 = (1000 / THERMOMETER_TASK_TICK_MS) as u32;
+// "sensor_noisy" warning is raised when rejections in a 10 seconds window reach threshold.
+const NOISY_WINDOW_TICKS : u32 = (10_000 / THERMOMETER_TASK_TICK_MS) as u32;
+const NOISY_REJECTION_THRESHOLD : u32 = 5;
+// Raw ADC statistics are taken over last 1 second.
+const RAW_WINDOW_TICKS : usize = (1000 / THERMOMETER_TASK_TICK_MS) as usize;
+pub const SENSOR_CHANNEL_NUM : usize = 2;
+
+// Temperature sensor channel. Value is index of per channel settings.
+#[derive(Copy, Clone)]
+pub enum SensorChannel
+{
+    Heater1 = 0,
+    // RP2040 internal temperature sensor
+    Cpu = 1,
+}
+
+impl SensorChannel
+{
+    pub fn from_name(name: &str) -> Option<Self>
+    {
+        match name {
+            "heater1" => Some(SensorChannel::Heater1),
+            "cpu" => Some(SensorChannel::Cpu),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str
+    {
+        match self {
+            SensorChannel::Heater1 => "heater1",
+            SensorChannel::Cpu => "cpu",
+        }
+    }
+
+    pub fn all() -> [SensorChannel; SENSOR_CHANNEL_NUM]
+    {
+        [SensorChannel::Heater1, SensorChannel::Cpu]
+    }
+
+    // Channel is NTC thermistor, and has ADC -> temperature conversion setting.
+    pub fn is_thermistor(&self) -> bool
+    {
+        match self {
+            SensorChannel::Heater1 => true,
+            SensorChannel::Cpu => false,
         }
     }
 }
 
 struct Thermometer
 {
+    filter : FilterChain,
+    plausibility : PlausibilityCheck,
+    channel : SensorChannel,
+}
+
+// Rejects sample which changes faster than physically possible from last accepted sample.
+struct PlausibilityCheck
+{
+    last_accepted : Option<f32>,
+    ticks_since_accepted : u32,
+    window_ticks : u32,
+    window_rejected : u32,
+    status : PlausibilityStatus,
+}
+
+#[derive(Copy, Clone)]
+pub struct PlausibilityStatus
+{
+    pub rejected : u32,
+    pub resynced : u32,
+    pub noisy : bool,
+}
+
+#[derive(Copy, Clone)]
+struct ThermometerReading
+{
+    // Calibrated, before filter chain.
+    unfiltered : f32,
+    // Filtered, before calibration.
+    raw : f32,
+    // Filtered and calibrated.
     temperature : f32,
-    exp_mov_ave_alpha : f32,
 }
 
 //
 // static const variables
 //
 
-// ADC -> celsius temperature conversion table
+// ADC -> celsius temperature conversion table, generated by build.rs from thermistor.toml
 // 1000 = 10.00[Celsius]
-static TEMPERATURE_TABLE : [i16; 411] = [
-    -7300,
-    -7300,
-    -6440,
-    -5901,
-    -5500,
-    -5177,
-    -4906,
-    -4671,
-    -4463,
-    -4275,
-    -4105,
-    -3948,
-    -3802,
-    -3666,
-    -3539,
-    -3419,
-    -3305,
-    -3196,
-    -3093,
-    -2995,
-    -2900,
-    -2809,
-    -2722,
-    -2637,
-    -2555,
-    -2476,
-    -2400,
-    -2326,
-    -2253,
-    -2183,
-    -2115,
-    -2048,
-    -1983,
-    -1919,
-    -1857,
-    -1796,
-    -1736,
-    -1678,
-    -1621,
-    -1565,
-    -1510,
-    -1456,
-    -1402,
-    -1350,
-    -1299,
-    -1248,
-    -1198,
-    -1149,
-    -1101,
-    -1053,
-    -1006,
-    -960,
-    -914,
-    -869,
-    -825,
-    -781,
-    -737,
-    -694,
-    -652,
-    -610,
-    -568,
-    -527,
-    -486,
-    -446,
-    -406,
-    -367,
-    -328,
-    -289,
-    -250,
-    -212,
-    -175,
-    -137,
-    -100,
-    -63,
-    -27,
-    9,
-    45,
-    81,
-    116,
-    151,
-    186,
-    221,
-    255,
-    289,
-    323,
-    357,
-    391,
-    424,
-    457,
-    490,
-    523,
-    555,
-    588,
-    620,
-    652,
-    684,
-    715,
-    747,
-    778,
-    810,
-    841,
-    872,
-    903,
-    933,
-    964,
-    994,
-    1025,
-    1055,
-    1085,
-    1115,
-    1145,
-    1174,
-    1204,
-    1233,
-    1263,
-    1292,
-    1321,
-    1351,
-    1380,
-    1409,
-    1437,
-    1466,
-    1495,
-    1523,
-    1552,
-    1581,
-    1609,
-    1637,
-    1666,
-    1694,
-    1722,
-    1750,
-    1778,
-    1806,
-    1834,
-    1862,
-    1890,
-    1917,
-    1945,
-    1973,
-    2000,
-    2028,
-    2055,
-    2083,
-    2110,
-    2138,
-    2165,
-    2192,
-    2220,
-    2247,
-    2274,
-    2302,
-    2329,
-    2356,
-    2383,
-    2410,
-    2437,
-    2464,
-    2492,
-    2519,
-    2546,
-    2573,
-    2600,
-    2627,
-    2654,
-    2681,
-    2708,
-    2735,
-    2762,
-    2789,
-    2816,
-    2843,
-    2870,
-    2897,
-    2924,
-    2951,
-    2978,
-    3005,
-    3032,
-    3059,
-    3086,
-    3113,
-    3141,
-    3168,
-    3195,
-    3222,
-    3249,
-    3277,
-    3304,
-    3331,
-    3358,
-    3386,
-    3413,
-    3441,
-    3468,
-    3495,
-    3523,
-    3550,
-    3578,
-    3606,
-    3633,
-    3661,
-    3689,
-    3717,
-    3744,
-    3772,
-    3800,
-    3828,
-    3856,
-    3884,
-    3913,
-    3941,
-    3969,
-    3997,
-    4026,
-    4054,
-    4083,
-    4111,
-    4140,
-    4169,
-    4198,
-    4227,
-    4256,
-    4285,
-    4314,
-    4343,
-    4372,
-    4402,
-    4431,
-    4461,
-    4490,
-    4520,
-    4550,
-    4580,
-    4610,
-    4640,
-    4670,
-    4700,
-    4731,
-    4761,
-    4792,
-    4823,
-    4853,
-    4884,
-    4916,
-    4947,
-    4978,
-    5010,
-    5041,
-    5073,
-    5105,
-    5137,
-    5169,
-    5201,
-    5234,
-    5266,
-    5299,
-    5332,
-    5365,
-    5398,
-    5431,
-    5465,
-    5498,
-    5532,
-    5566,
-    5600,
-    5635,
-    5669,
-    5704,
-    5739,
-    5774,
-    5809,
-    5845,
-    5880,
-    5916,
-    5952,
-    5989,
-    6025,
-    6062,
-    6099,
-    6136,
-    6174,
-    6212,
-    6250,
-    6288,
-    6326,
-    6365,
-    6404,
-    6443,
-    6483,
-    6523,
-    6563,
-    6603,
-    6644,
-    6685,
-    6727,
-    6768,
-    6810,
-    6853,
-    6895,
-    6939,
-    6982,
-    7026,
-    7070,
-    7114,
-    7159,
-    7205,
-    7251,
-    7297,
-    7343,
-    7390,
-    7438,
-    7486,
-    7534,
-    7583,
-    7633,
-    7683,
-    7733,
-    7784,
-    7835,
-    7888,
-    7940,
-    7993,
-    8047,
-    8102,
-    8157,
-    8213,
-    8269,
-    8326,
-    8384,
-    8443,
-    8502,
-    8562,
-    8623,
-    8685,
-    8748,
-    8811,
-    8876,
-    8941,
-    9007,
-    9075,
-    9143,
-    9212,
-    9283,
-    9354,
-    9427,
-    9501,
-    9577,
-    9653,
-    9731,
-    9810,
-    9891,
-    9974,
-    10058,
-    10143,
-    10230,
-    10319,
-    10410,
-    10503,
-    10598,
-    10695,
-    10794,
-    10896,
-    10999,
-    11106,
-    11215,
-    11327,
-    11442,
-    11560,
-    11681,
-    11806,
-    11934,
-    12067,
-    12203,
-    12344,
-    12490,
-    12640,
-    12796,
-    12958,
-    13125,
-    13300,
-    13481,
-    13670,
-    13867,
-    14074,
-    14290,
-    14517,
-    14756,
-    15008,
-    15274,
-    15557,
-    15858,
-    16179,
-    16523,
-    16894,
-    17296,
-    17732,
-    18211,
-    18740,
-    19329,
-    19992,
-    20749,
-    21626,
-    22665,
-    23929,
-    25525,
-    27652,
-    30748,
-    32767,
-    32767,
-    32767,
-];
+include!(concat!(env!("OUT_DIR"), "/temperature_table.rs"));
+
+// RP2040 internal sensor is about 0.5 celsius per ADC count and noisy, so it is filtered more than thermistor.
+const CPU_FILTER_CONFIG : FilterConfig = FilterConfig {
+    oversampling: OVERSAMPLING_MAX,
+    median_window: 5,
+    smoothing: Smoothing::Ema { alpha: 0.05 },
+    kalman: None,
+};
 
 //
 // static variables
 //
 static HEATER1_TEMP : Mutex<ThreadModeRawMutex, RefCell<f32>> = Mutex::new(RefCell::new(0.0));
+static HEATER1_RAW_TEMP : Mutex<ThreadModeRawMutex, RefCell<f32>> = Mutex::new(RefCell::new(0.0));
+static HEATER1_UNFILTERED_TEMP : Mutex<ThreadModeRawMutex, RefCell<f32>> = Mutex::new(RefCell::new(0.0));
+static FILTER_CONFIGS : Mutex<ThreadModeRawMutex, RefCell<[FilterConfig; SENSOR_CHANNEL_NUM]>> = Mutex::new(RefCell::new([FilterConfig::new(), CPU_FILTER_CONFIG]));
+static CONVERSIONS : Mutex<ThreadModeRawMutex, RefCell<[Conversion; SENSOR_CHANNEL_NUM]>> = Mutex::new(RefCell::new([Conversion::Table; SENSOR_CHANNEL_NUM]));
 static HEATER2_TEMP : Mutex<ThreadModeRawMutex, RefCell<f32>> = Mutex::new(RefCell::new(0.0));
 static CPU_TEMP : Mutex<ThreadModeRawMutex, RefCell<f32>> = Mutex::new(RefCell::new(0.0));
+static CPU_RAW_TEMP : Mutex<ThreadModeRawMutex, RefCell<f32>> = Mutex::new(RefCell::new(0.0));
+static CPU_UNFILTERED_TEMP : Mutex<ThreadModeRawMutex, RefCell<f32>> = Mutex::new(RefCell::new(0.0));
+static AMBIENT_TEMP : Mutex<ThreadModeRawMutex, RefCell<Option<f32>>> = Mutex::new(RefCell::new(None));
+static AMBIENT_HUMIDITY : Mutex<ThreadModeRawMutex, RefCell<Option<f32>>> = Mutex::new(RefCell::new(None));
+static PLAUSIBILITY : Mutex<ThreadModeRawMutex, RefCell<[PlausibilityStatus; SENSOR_CHANNEL_NUM]>> = Mutex::new(RefCell::new([PlausibilityStatus::new(); SENSOR_CHANNEL_NUM]));
+static RAW_SUMMARIES : Mutex<ThreadModeRawMutex, RefCell<[RawSummary; ADC_SAMPLED_CHANNEL_NUM]>> = Mutex::new(RefCell::new([RawSummary::new(); ADC_SAMPLED_CHANNEL_NUM]));
 
 impl Thermometer
 {
-    pub fn new(alpha: f32) -> Self {
+    pub fn new(channel: SensorChannel) -> Self {
         Self { 
-            temperature: 0.0,
-            exp_mov_ave_alpha: alpha
+            filter: FilterChain::new(filter_config(channel)),
+            plausibility: PlausibilityCheck::new(),
+            channel: channel,
+        }
+    }
+
+    // Returns None if sample is rejected by plausibility check.
+    pub fn calc_next(&mut self, adc_value: u16) -> Option<ThermometerReading>
+    {
+        // Follow filter config changed at runtime.
+        self.filter.set_config(filter_config(self.channel));
+
+        let calibration = calibration(self.channel);
+        let current_temp : f32 = match self.channel {
+            SensorChannel::Heater1 => convert_adc_to_temperature(&conversion(self.channel), adc_value),
+            SensorChannel::Cpu => convert_to_celsius(adc_value, adc_vref()),
+        };
+
+        let accepted = self.plausibility.check(current_temp);
+        let status = self.plausibility.status;
+        PLAUSIBILITY.lock(|lock| {
+            lock.borrow_mut()[self.channel as usize] = status;
+        });
+        if !accepted {
+            return None;
+        }
+        let raw = self.filter.apply(current_temp);
+
+        // Calibration is linear, so correcting filtered value equals filtering corrected values.
+        Some(ThermometerReading {
+            unfiltered: calibration.apply(current_temp),
+            raw: raw,
+            temperature: calibration.apply(raw),
+        })
+    }
+}
+
+impl PlausibilityStatus
+{
+    pub const fn new() -> Self
+    {
+        Self { rejected: 0, resynced: 0, noisy: false }
+    }
+}
+
+impl PlausibilityCheck
+{
+    pub fn new() -> Self
+    {
+        Self {
+            last_accepted: None,
+            ticks_since_accepted: 0,
+            window_ticks: 0,
+            window_rejected: 0,
+            status: PlausibilityStatus::new(),
         }
     }
 
-    pub fn calc_next(&mut self, adc_value: u16) -> f32
+    // Called every thermometer tick. Returns true if sample is accepted.
+    pub fn check(&mut self, temperature: f32) -> bool
     {
-        let current_temp : f32 = get_temperature_from_table(adc_value);
-        self.temperature = (current_temp * self.exp_mov_ave_alpha) + ((1.0-self.exp_mov_ave_alpha) * self.temperature);
+        self.ticks_since_accepted += 1;
+        let elapsed_s = (self.ticks_since_accepted as u64 * THERMOMETER_TASK_TICK_MS) as f32 / 1000.0;
+
+        let accepted = match self.last_accepted {
+            None => true,
+            Some(last) => (temperature - last).abs() <= PLAUSIBLE_RATE_CELCIUS_PER_S * elapsed_s + PLAUSIBLE_STEP_MARGIN_CELCIUS,
+        };
+        // Rejected for a long time, the change is real (e.g. disconnected thermistor).
+        let resync = !accepted && self.ticks_since_accepted >= PLAUSIBILITY_RESYNC_TICKS;
+
+        if accepted || resync {
+            self.last_accepted = Some(temperature);
+            self.ticks_since_accepted = 0;
+        }
+        if resync {
+            self.status.resynced += 1;
+        }
+        if !accepted {
+            self.status.rejected += 1;
+            self.window_rejected += 1;
+        }
+
+        // Warning is held for one window after rejections calm down.
+        self.window_ticks += 1;
+        if self.window_rejected >= NOISY_REJECTION_THRESHOLD {
+            self.status.noisy = true;
+        }
+        if self.window_ticks >= NOISY_WINDOW_TICKS {
+            self.status.noisy = self.window_rejected >= NOISY_REJECTION_THRESHOLD;
+            self.window_ticks = 0;
+            self.window_rejected = 0;
+        }
 
-        self.temperature
+        accepted || resync
+    }
+}
+
+// NTC thermistor channel sampled by thermometer_task. read() returns latest filtered and calibrated value.
+pub struct ThermistorSensor
+{
+    channel : SensorChannel,
+    adc_index : usize,
+}
+
+impl ThermistorSensor
+{
+    pub fn new(channel: SensorChannel) -> Self
+    {
+        // Index in ADC_SAMPLED_INPUTS
+        let adc_index = match channel {
+            SensorChannel::Heater1 => 0,
+            SensorChannel::Cpu => 2,
+        };
+        Self { channel: channel, adc_index: adc_index }
+    }
+}
+
+impl TemperatureSensor for ThermistorSensor
+{
+    fn identity(&self) -> &'static str
+    {
+        self.channel.name()
+    }
+
+    async fn read(&mut self) -> Result<Temperature, SensorFault>
+    {
+        // divider_supply --- NTC --- ADC input --- series_resistor --- GND
+        // Open NTC pulls ADC input to GND, shorted NTC pulls it to supply.
+        // Latest block average is checked, single glitch sample in raw window is not a fault.
+        if self.channel.is_thermistor() {
+            match raw_summary(self.adc_index).latest {
+                Some(level) if level <= ADC_SATURATION_MARGIN => return Err(SensorFault::Open),
+                Some(level) if level >= ADC_MAX_VALUE - ADC_SATURATION_MARGIN => return Err(SensorFault::ShortToVcc),
+                Some(_) => {}
+                // ADC DMA delivers no samples.
+                None => return Err(SensorFault::Bus),
+            }
+        }
+
+        Ok(calibrated_temperature(self.channel))
     }
 }
 
 #[embassy_executor::task]
-pub async fn thermometer_task(mut adcio: ADCIo<'static, PIN_26, PIN_27>)
+pub async fn thermometer_task(mut adcio: ADCIo<'static, PIN_26, PIN_27, PIN_28>)
 {
 
-    let mut heater1_temp = Thermometer::new(0.22);
+    let mut heater1_temp = Thermometer::new(SensorChannel::Heater1);
+    let mut heater1_current = CurrentSensor::new(0.22);
+    let mut cpu_temp = Thermometer::new(SensorChannel::Cpu);
     //let mut heater2_temp = Thermometer::new(0.22);
-    let mut ticker = Ticker::every(Duration::from_millis(20));
+    let mut raw_windows : [RawWindow<RAW_WINDOW_TICKS>; ADC_SAMPLED_CHANNEL_NUM] = core::array::from_fn(|_| RawWindow::new());
+    let mut ticker = Ticker::every(Duration::from_millis(THERMOMETER_TASK_TICK_MS));
+    init_loop_timing(TimedLoop::Thermometer, THERMOMETER_TASK_TICK_MS);
 
     loop {
-        let heater1_level = adcio.adc.read(&mut adcio.heater1).await;
-        let heater1_current_temp = heater1_temp.calc_next(heater1_level);
+        record_loop_tick(TimedLoop::Thermometer);
+
+        // Samples DMA collected since last tick. Current sense always averages whole block.
+        let oversampling = [filter_config(SensorChannel::Heater1).oversampling, OVERSAMPLING_MAX, filter_config(SensorChannel::Cpu).oversampling];
+        let blocks = adcio.adc_dma.collect(oversampling);
+        let [heater1_block, heater1_current_block, cpu_block] = blocks;
+
+        for (window, block) in raw_windows.iter_mut().zip(blocks.iter()) {
+            window.push(*block);
+        }
+        RAW_SUMMARIES.lock(|lock| {
+            for (summary, window) in lock.borrow_mut().iter_mut().zip(raw_windows.iter()) {
+                *summary = window.summary();
+            }
+        });
+
+        if let Some(heater1_reading) = heater1_block.oversampled().and_then(|level| heater1_temp.calc_next(level)) {
+            record_stats(SensorChannel::Heater1, Temperature::from_celsius(heater1_reading.temperature), THERMOMETER_TASK_TICK_MS);
+            HEATER1_TEMP.lock(|lock| {
+                *lock.borrow_mut() = heater1_reading.temperature
+            });
+            HEATER1_RAW_TEMP.lock(|lock| {
+                *lock.borrow_mut() = heater1_reading.raw
+            });
+            HEATER1_UNFILTERED_TEMP.lock(|lock| {
+                *lock.borrow_mut() = heater1_reading.unfiltered
+            });
+        }
         //log::info!("Pin 31 ADC: {}", heater1_level);
         
-        //let heater2_level = adcio.adc.read(&mut adcio.heater2).await;
         //let heater2_current_temp = heater2_temp.calc_next(heater2_level);
         //info!("Pin 32 ADC: {}", level);
         
-        let cputemp = adcio.adc.read_temperature().await;
-        //info!("Temp: {} degrees", convert_to_celsius(cputemp));
+        if let Some(heater1_current_level) = heater1_current_block.average() {
+            match heater1_current.calc_next(heater1_current_level) {
+                Ok(current) => {
+                    set_heater1_current(current);
+                    set_heater1_current_fault(None);
+                }
+                Err(fault) => set_heater1_current_fault(Some(fault)),
+            }
+        }
 
-        HEATER1_TEMP.lock(|lock| {
-            *lock.borrow_mut() = heater1_current_temp
-        });
         // HEATER2_TEMP.lock(|lock| {
         //    *lock.borrow_mut() = heater2_current_temp
         //});
-        CPU_TEMP.lock(|lock| {
-            *lock.borrow_mut() = convert_to_celsius(cputemp);
-        });
+        if let Some(cpu_reading) = cpu_block.oversampled().and_then(|level| cpu_temp.calc_next(level)) {
+            record_stats(SensorChannel::Cpu, Temperature::from_celsius(cpu_reading.temperature), THERMOMETER_TASK_TICK_MS);
+            CPU_TEMP.lock(|lock| {
+                *lock.borrow_mut() = cpu_reading.temperature
+            });
+            CPU_RAW_TEMP.lock(|lock| {
+                *lock.borrow_mut() = cpu_reading.raw
+            });
+            CPU_UNFILTERED_TEMP.lock(|lock| {
+                *lock.borrow_mut() = cpu_reading.unfiltered
+            });
+        }
 
         ticker.next().await;
     }
 }
 
-fn get_temperature_from_table(adc_value: u16) -> f32
+// (table index, interpolation ratio to next record) used for ADC value.
+pub fn table_position(adc_value: u16) -> (usize, f32)
 {
-    // Clipping 12bit ADC range
-    let v : usize = if adc_value < 4096 { adc_value } else { 4095 } as usize;
-    
-    // temperature table has record that every tens digit.
+    // Clipping ADC range
+    let v : usize = (adc_value as usize).min(ADC_FULL_SCALE - 1);
+
+    // temperature table has record that every TEMPERATURE_TABLE_STEP digits.
     // The temperature corresponding to one digit of ADC is linearly interpolated.
-    let adc_a: usize = (v / 10) as usize;
+    let adc_a: usize = v / TEMPERATURE_TABLE_STEP;
+    let alpha : f32 = (v % TEMPERATURE_TABLE_STEP) as f32 / TEMPERATURE_TABLE_STEP as f32;
+
+    (adc_a, alpha)
+}
+
+fn get_temperature_from_table(adc_value: u16) -> f32
+{
+    let (adc_a, alpha) = table_position(adc_value);
 
     let temp_a : f32 = TEMPERATURE_TABLE[adc_a] as f32 / 100.0;
     let temp_b : f32 = TEMPERATURE_TABLE[adc_a + 1] as f32 / 100.0;
-    let alpha : f32 = (v % 10) as f32 / 10.0;
-    
+
     // Return temperature
     (temp_a * (1.0 - alpha)) + (temp_b * alpha)
 }
 
-fn convert_to_celsius(raw_temp: u16) -> f32
+fn convert_adc_to_temperature(conversion: &Conversion, adc_value: u16) -> f32
+{
+    // Table is also used when formula gives no sane value, its records are always finite.
+    match formula_temperature(conversion, &THERMISTOR_CIRCUIT, adc_value) {
+        Some(temperature) => temperature,
+        None => get_temperature_from_table(adc_value),
+    }
+}
+
+fn convert_to_celsius(raw_temp: u16, vref: f32) -> f32
 {
     // According to chapter 4.9.5. Temperature Sensor in RP2040 datasheet
-    27.0 - (raw_temp as f32 * 3.3 / 4096.0 - 0.706) / 0.001721 as f32
+    // Nominal 0.706V at 27 celsius and -1.721mV/celsius, remaining device error is corrected by calibration.
+    27.0 - (raw_temp as f32 * vref / 4096.0 - 0.706) / 0.001721 as f32
 }
 
-pub fn heater1_temperature() -> f32
+pub fn heater1_temperature() -> Temperature
 {
     let temperature = HEATER1_TEMP.lock(|lock| {
         *(lock.borrow_mut())
     });
-    (temperature * 100.0 + 0.5).round() / 100.0
+    Temperature::from_celsius(temperature)
+}
+
+// Temperature before calibration is applied.
+pub fn heater1_raw_temperature() -> Temperature
+{
+    let temperature = HEATER1_RAW_TEMP.lock(|lock| {
+        *(lock.borrow_mut())
+    });
+    Temperature::from_celsius(temperature)
+}
+
+// Temperature before filter chain is applied.
+pub fn heater1_unfiltered_temperature() -> Temperature
+{
+    let temperature = HEATER1_UNFILTERED_TEMP.lock(|lock| {
+        *(lock.borrow_mut())
+    });
+    Temperature::from_celsius(temperature)
+}
+
+pub fn unfiltered_temperature(channel: SensorChannel) -> Temperature
+{
+    match channel {
+        SensorChannel::Heater1 => heater1_unfiltered_temperature(),
+        SensorChannel::Cpu => cpu_unfiltered_temperature(),
+    }
+}
+
+pub fn raw_temperature(channel: SensorChannel) -> Temperature
+{
+    match channel {
+        SensorChannel::Heater1 => heater1_raw_temperature(),
+        SensorChannel::Cpu => cpu_raw_temperature(),
+    }
+}
+
+pub fn calibrated_temperature(channel: SensorChannel) -> Temperature
+{
+    match channel {
+        SensorChannel::Heater1 => heater1_temperature(),
+        SensorChannel::Cpu => cpu_temperature(),
+    }
 }
 
 /*
-pub fn heater2_temperature() -> f32
+pub fn heater2_temperature() -> Temperature
 {
     let temperature = HEATER2_TEMP.lock(|lock| {
         *(lock.borrow_mut())
     });
-    (temperature * 100.0 + 0.5).round() / 100.0
+    Temperature::from_celsius(temperature)
 }
 */
 
-pub fn cpu_temperature() -> f32
+pub fn cpu_temperature() -> Temperature
 {
     let temperature = CPU_TEMP.lock(|lock| {
         *(lock.borrow_mut())
     });
-    (temperature * 100.0 + 0.5).round() / 100.0
+    Temperature::from_celsius(temperature)
+}
+
+// Temperature before calibration is applied.
+pub fn cpu_raw_temperature() -> Temperature
+{
+    let temperature = CPU_RAW_TEMP.lock(|lock| {
+        *(lock.borrow_mut())
+    });
+    Temperature::from_celsius(temperature)
 }
 
+// Temperature before filter chain is applied.
+pub fn cpu_unfiltered_temperature() -> Temperature
+{
+    let temperature = CPU_UNFILTERED_TEMP.lock(|lock| {
+        *(lock.borrow_mut())
+    });
+    Temperature::from_celsius(temperature)
+}
+
+pub fn plausibility(channel: SensorChannel) -> PlausibilityStatus
+{
+    PLAUSIBILITY.lock(|lock| {
+        lock.borrow_mut()[channel as usize]
+    })
+}
+
+// True if any channel keeps rejecting implausible samples.
+pub fn sensor_noisy_warning() -> bool
+{
+    PLAUSIBILITY.lock(|lock| {
+        lock.borrow().iter().any(|status| status.noisy)
+    })
+}
+
+// Raw ADC statistics of sampled channel, index is ADC_SAMPLED_INPUTS order.
+pub fn raw_summary(index: usize) -> RawSummary
+{
+    RAW_SUMMARIES.lock(|lock| {
+        lock.borrow_mut()[index]
+    })
+}
+
+pub fn set_ambient(temperature: Option<f32>, humidity: Option<f32>)
+{
+    AMBIENT_TEMP.lock(|lock| {
+        *lock.borrow_mut() = temperature
+    });
+    AMBIENT_HUMIDITY.lock(|lock| {
+        *lock.borrow_mut() = humidity
+    });
+}
+
+// Return None if ambient sensor is not connected.
+pub fn ambient_temperature() -> Option<Temperature>
+{
+    let temperature = AMBIENT_TEMP.lock(|lock| {
+        *(lock.borrow_mut())
+    });
+    temperature.map(|t| Temperature::from_celsius(t))
+}
+
+pub fn ambient_humidity() -> Option<f32>
+{
+    let humidity = AMBIENT_HUMIDITY.lock(|lock| {
+        *(lock.borrow_mut())
+    });
+    humidity.map(|h| (h * 100.0 + 0.5).round() / 100.0)
+}
+
+pub fn conversion(channel: SensorChannel) -> Conversion
+{
+    CONVERSIONS.lock(|lock| {
+        lock.borrow_mut()[channel as usize]
+    })
+}
+
+pub fn set_conversion(channel: SensorChannel, conversion: Conversion)
+{
+    CONVERSIONS.lock(|lock| {
+        lock.borrow_mut()[channel as usize] = conversion;
+    });
+}
+
+// Formula parameters of the thermistor which conversion table is generated from.
+pub fn default_formula() -> Conversion
+{
+    THERMISTOR_FORMULA
+}
+
+pub fn filter_config(channel: SensorChannel) -> FilterConfig
+{
+    FILTER_CONFIGS.lock(|lock| {
+        lock.borrow_mut()[channel as usize]
+    })
+}
+
+pub fn set_filter_config(channel: SensorChannel, config: FilterConfig) -> Result<(), &'static str>
+{
+    config.validate()?;
+    FILTER_CONFIGS.lock(|lock| {
+        lock.borrow_mut()[channel as usize] = config;
+    });
+
+    Ok(())
+}
@@ -0,0 +1,312 @@
+// WebSocket (RFC 6455) handshake and framing.
+// This module does not touch socket, so frames can be parsed and built on host.
+use core::str::from_utf8;
+
+use alloc::string::String;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use sha1::{Digest, Sha1};
+
+//
+// static const variables
+//
+const WEBSOCKET_GUID : &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+// 2 bytes + 8 bytes extended payload length. Server frames are not masked.
+pub const FRAME_HEADER_MAX : usize = 10;
+// Payload of control frames (close, ping, pong) is at most 125 bytes.
+pub const CONTROL_PAYLOAD_MAX : usize = 125;
+
+// Close status codes.
+pub const CLOSE_NORMAL : u16 = 1000;
+pub const CLOSE_GOING_AWAY : u16 = 1001;
+pub const CLOSE_PROTOCOL_ERROR : u16 = 1002;
+pub const CLOSE_UNSUPPORTED_DATA : u16 = 1003;
+pub const CLOSE_INVALID_PAYLOAD : u16 = 1007;
+pub const CLOSE_MESSAGE_TOO_BIG : u16 = 1009;
+
+pub type FrameHeaderBytes = heapless::Vec<u8, FRAME_HEADER_MAX>;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Opcode
+{
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct FrameHeader
+{
+    // Last frame of message.
+    pub fin : bool,
+    pub opcode : Opcode,
+    // Client to server frames are always masked.
+    pub mask : Option<[u8; 4]>,
+    pub header_len : usize,
+    pub payload_len : usize,
+}
+
+impl Opcode
+{
+    fn from_bits(bits: u8) -> Option<Self>
+    {
+        match bits {
+            0x0 => Some(Opcode::Continuation),
+            0x1 => Some(Opcode::Text),
+            0x2 => Some(Opcode::Binary),
+            0x8 => Some(Opcode::Close),
+            0x9 => Some(Opcode::Ping),
+            0xA => Some(Opcode::Pong),
+            _ => None,
+        }
+    }
+
+    fn bits(self) -> u8
+    {
+        match self {
+            Opcode::Continuation => 0x0,
+            Opcode::Text => 0x1,
+            Opcode::Binary => 0x2,
+            Opcode::Close => 0x8,
+            Opcode::Ping => 0x9,
+            Opcode::Pong => 0xA,
+        }
+    }
+
+    pub fn is_control(self) -> bool
+    {
+        matches!(self, Opcode::Close | Opcode::Ping | Opcode::Pong)
+    }
+}
+
+impl FrameHeader
+{
+    // Header and payload length. usize::MAX on overflow, it never fits in buffer.
+    pub fn frame_len(&self) -> usize
+    {
+        self.header_len.saturating_add(self.payload_len)
+    }
+}
+
+// Value of "Sec-WebSocket-Accept" for "Sec-WebSocket-Key" of client.
+pub fn accept_key(key: &str) -> String
+{
+    let mut sha1 = Sha1::new();
+    sha1.update(key.trim().as_bytes());
+    sha1.update(WEBSOCKET_GUID.as_bytes());
+
+    STANDARD.encode(sha1.finalize())
+}
+
+// Parse frame header at head of buf. None if header is not received completely yet.
+// Payload may be still incomplete, check frame_len().
+// Frame longer than max_frame_len is rejected, so frame_len() never exceeds it.
+// Error is close status code and reason.
+pub fn parse_frame_header(buf: &[u8], max_frame_len: usize) -> Result<Option<FrameHeader>, (u16, String)>
+{
+    if buf.len() < 2 {
+        return Ok(None);
+    }
+
+    let fin = (buf[0] & 0x80) != 0;
+    // No extension is negotiated, so RSV1-3 must be 0.
+    if (buf[0] & 0x70) != 0 {
+        return Err((CLOSE_PROTOCOL_ERROR, String::from("reserved bits are set")));
+    }
+    let opcode = Opcode::from_bits(buf[0] & 0x0F).ok_or_else(|| (CLOSE_PROTOCOL_ERROR, format!("unknown opcode {:#x}", buf[0] & 0x0F)))?;
+    let masked = (buf[1] & 0x80) != 0;
+
+    let (payload_len, mut header_len) = match buf[1] & 0x7F {
+        126 => {
+            if buf.len() < 4 {
+                return Ok(None);
+            }
+            (u16::from_be_bytes([buf[2], buf[3]]) as u64, 4)
+        }
+        127 => {
+            if buf.len() < 10 {
+                return Ok(None);
+            }
+            let mut len = [0u8; 8];
+            len.copy_from_slice(&buf[2..10]);
+            (u64::from_be_bytes(len), 10)
+        }
+        n => (n as u64, 2),
+    };
+
+    if opcode.is_control() && (!fin || payload_len > CONTROL_PAYLOAD_MAX as u64) {
+        return Err((CLOSE_PROTOCOL_ERROR, String::from("invalid control frame")));
+    }
+    // Mask is counted too, header_len is at most FRAME_HEADER_MAX + 4.
+    let frame_header_len = header_len + if masked { 4 } else { 0 };
+    if payload_len > max_frame_len.saturating_sub(frame_header_len) as u64 {
+        return Err((CLOSE_MESSAGE_TOO_BIG, String::from("message too large")));
+    }
+    let payload_len = payload_len as usize;
+
+    let mask = if masked {
+        if buf.len() < header_len + 4 {
+            return Ok(None);
+        }
+        let mut mask = [0u8; 4];
+        mask.copy_from_slice(&buf[header_len..header_len + 4]);
+        header_len += 4;
+        Some(mask)
+    } else {
+        None
+    };
+
+    Ok(Some(FrameHeader { fin: fin, opcode: opcode, mask: mask, header_len: header_len, payload_len: payload_len }))
+}
+
+// Masking is XOR, so same function unmasks payload.
+pub fn apply_mask(payload: &mut [u8], mask: [u8; 4])
+{
+    for (i, b) in payload.iter_mut().enumerate() {
+        *b ^= mask[i % 4];
+    }
+}
+
+// Header of unfragmented, unmasked frame sent by server.
+pub fn encode_frame_header(opcode: Opcode, payload_len: usize) -> FrameHeaderBytes
+{
+    let mut header = FrameHeaderBytes::new();
+    // Capacity is enough for every length, so push never fails.
+    let _ = header.push(0x80 | opcode.bits());
+    if payload_len < 126 {
+        let _ = header.push(payload_len as u8);
+    }
+    else if payload_len <= u16::MAX as usize {
+        let _ = header.push(126);
+        let _ = header.extend_from_slice(&(payload_len as u16).to_be_bytes());
+    }
+    else {
+        let _ = header.push(127);
+        let _ = header.extend_from_slice(&(payload_len as u64).to_be_bytes());
+    }
+
+    header
+}
+
+// Payload of close frame sent by server. Reason is omitted.
+pub fn close_payload(code: u16) -> [u8; 2]
+{
+    code.to_be_bytes()
+}
+
+// Status code and reason of received close frame. Status code is optional.
+pub fn parse_close_payload(payload: &[u8]) -> Result<(Option<u16>, &str), String>
+{
+    match payload.len() {
+        0 => Ok((None, "")),
+        1 => Err(String::from("invalid close payload")),
+        _ => {
+            let reason = from_utf8(&payload[2..]).map_err(|_| String::from("close reason is not UTF-8"))?;
+            Ok((Some(u16::from_be_bytes([payload[0], payload[1]])), reason))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const BUF_LEN : usize = 2048;
+
+    #[test]
+    fn accept_key_rfc_sample()
+    {
+        // RFC 6455 section 1.3
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn payload_length_encodings()
+    {
+        for (len, header_len) in [(125usize, 2usize), (126, 4), (65535, 4), (65536, 10)] {
+            let header = encode_frame_header(Opcode::Binary, len);
+            assert_eq!(header.len(), header_len);
+
+            let frame = parse_frame_header(&header, usize::MAX).unwrap().unwrap();
+            assert_eq!(frame, FrameHeader { fin: true, opcode: Opcode::Binary, mask: None, header_len: header_len, payload_len: len });
+            assert_eq!(frame.frame_len(), header_len + len);
+        }
+    }
+
+    #[test]
+    fn partial_header()
+    {
+        assert_eq!(parse_frame_header(&[], BUF_LEN), Ok(None));
+        assert_eq!(parse_frame_header(&[0x81], BUF_LEN), Ok(None));
+        assert_eq!(parse_frame_header(&[0x82, 126, 0x01], BUF_LEN), Ok(None));
+        assert_eq!(parse_frame_header(&[0x82, 127, 0, 0, 0, 0, 0, 0, 0x01], BUF_LEN), Ok(None));
+        // Mask key is not received completely.
+        assert_eq!(parse_frame_header(&[0x81, 0x85, 0x37, 0xfa, 0x21], BUF_LEN), Ok(None));
+    }
+
+    #[test]
+    fn masked_text_frame()
+    {
+        // RFC 6455 section 5.7, masked "Hello"
+        let mut buf = [0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58];
+        let frame = parse_frame_header(&buf, BUF_LEN).unwrap().unwrap();
+        assert_eq!(frame.opcode, Opcode::Text);
+        assert_eq!(frame.header_len, 6);
+        assert_eq!(frame.frame_len(), buf.len());
+
+        let mask = frame.mask.unwrap();
+        let payload = &mut buf[frame.header_len..frame.frame_len()];
+        apply_mask(payload, mask);
+        assert_eq!(payload, b"Hello");
+        apply_mask(payload, mask);
+        assert_eq!(payload, &[0x7f, 0x9f, 0x4d, 0x51, 0x58]);
+    }
+
+    #[test]
+    fn invalid_frames()
+    {
+        let protocol_error = |buf: &[u8]| matches!(parse_frame_header(buf, BUF_LEN), Err((CLOSE_PROTOCOL_ERROR, _)));
+
+        // Fragmented ping
+        assert!(protocol_error(&[0x09, 0x00]));
+        // Close with 126 bytes payload
+        assert!(protocol_error(&[0x88, 126, 0x00, 126]));
+        // RSV1
+        assert!(protocol_error(&[0xC1, 0x00]));
+        // Reserved opcode
+        assert!(protocol_error(&[0x83, 0x00]));
+    }
+
+    #[test]
+    fn oversized_frame()
+    {
+        // Declared length does not fit in buffer, must not overflow header_len + payload_len.
+        let huge = [0x82, 127, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+        assert!(matches!(parse_frame_header(&huge, BUF_LEN), Err((CLOSE_MESSAGE_TOO_BIG, _))));
+        let u32_max = [0x82, 127, 0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF];
+        assert!(matches!(parse_frame_header(&u32_max, BUF_LEN), Err((CLOSE_MESSAGE_TOO_BIG, _))));
+
+        // Masked frame which fills buffer exactly is accepted, one more byte is not.
+        let fits = [0x82, 0xFE, ((BUF_LEN - 8) >> 8) as u8, (BUF_LEN - 8) as u8, 1, 2, 3, 4];
+        assert_eq!(parse_frame_header(&fits, BUF_LEN).unwrap().unwrap().frame_len(), BUF_LEN);
+        let too_big = [0x82, 0xFE, ((BUF_LEN - 7) >> 8) as u8, (BUF_LEN - 7) as u8, 1, 2, 3, 4];
+        assert!(matches!(parse_frame_header(&too_big, BUF_LEN), Err((CLOSE_MESSAGE_TOO_BIG, _))));
+
+        let header = FrameHeader { fin: true, opcode: Opcode::Binary, mask: None, header_len: 10, payload_len: usize::MAX };
+        assert_eq!(header.frame_len(), usize::MAX);
+    }
+
+    #[test]
+    fn close_payload_round_trip()
+    {
+        assert_eq!(parse_close_payload(&[]), Ok((None, "")));
+        assert!(parse_close_payload(&[0x03]).is_err());
+        assert_eq!(parse_close_payload(&close_payload(CLOSE_GOING_AWAY)), Ok((Some(CLOSE_GOING_AWAY), "")));
+        assert_eq!(parse_close_payload(&[0x03, 0xE8, b'b', b'y', b'e']), Ok((Some(CLOSE_NORMAL), "bye")));
+        assert!(parse_close_payload(&[0x03, 0xE8, 0xFF]).is_err());
+    }
+}
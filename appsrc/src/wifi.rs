@@ -0,0 +1,50 @@
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::{Mutex, raw::ThreadModeRawMutex};
+use embassy_sync::signal::Signal;
+
+// Wi-Fi RSSI for /metrics, built with "wifi-rssi" feature only.
+// cyw43 has no public RSSI API, so this sends raw WLC_GET_RSSI ioctl and needs cyw43 which exposes Control::ioctl.
+
+//
+// static const variables
+//
+// cyw43 ioctl command, returns RSSI of joined AP in dBm.
+const WLC_GET_RSSI : u32 = 127;
+
+//
+// static variables
+//
+static WIFI_RSSI : Mutex<ThreadModeRawMutex, RefCell<Option<i32>>> = Mutex::new(RefCell::new(None));
+static RSSI_REQUEST : Signal<ThreadModeRawMutex, ()> = Signal::new();
+
+// Ask task which owns Control to read RSSI. Read value is reported by wifi_rssi() afterwards.
+pub fn request_wifi_rssi()
+{
+    RSSI_REQUEST.signal(());
+}
+
+// Read RSSI from cyw43 if requested. Called by task which owns Control.
+pub async fn update_wifi_rssi(control: &mut cyw43::Control<'static>)
+{
+    if !RSSI_REQUEST.signaled() {
+        return;
+    }
+    RSSI_REQUEST.reset();
+
+    let mut buf = [0u8; 4];
+    control.ioctl(cyw43::IoctlType::Get, WLC_GET_RSSI, 0, &mut buf).await;
+    let rssi = i32::from_le_bytes(buf);
+
+    WIFI_RSSI.lock(|lock| {
+        *(lock.borrow_mut()) = Some(rssi);
+    });
+}
+
+// None until first update.
+pub fn wifi_rssi() -> Option<i32>
+{
+    WIFI_RSSI.lock(|lock| {
+        *(lock.borrow_mut())
+    })
+}
@@ -0,0 +1,417 @@
+// Hand-made table which was in thermometer.rs before build.rs generated it, kept verbatim.
+// build.rs checks that generated table for the same circuit equals this.
+// ADC -> celsius temperature conversion table
+// 1000 = 10.00[Celsius]
+static TEMPERATURE_TABLE : [i16; 411] = [
+    -7300,
+    -7300,
+    -6440,
+    -5901,
+    -5500,
+    -5177,
+    -4906,
+    -4671,
+    -4463,
+    -4275,
+    -4105,
+    -3948,
+    -3802,
+    -3666,
+    -3539,
+    -3419,
+    -3305,
+    -3196,
+    -3093,
+    -2995,
+    -2900,
+    -2809,
+    -2722,
+    -2637,
+    -2555,
+    -2476,
+    -2400,
+    -2326,
+    -2253,
+    -2183,
+    -2115,
+    -2048,
+    -1983,
+    -1919,
+    -1857,
+    -1796,
+    -1736,
+    -1678,
+    -1621,
+    -1565,
+    -1510,
+    -1456,
+    -1402,
+    -1350,
+    -1299,
+    -1248,
+    -1198,
+    -1149,
+    -1101,
+    -1053,
+    -1006,
+    -960,
+    -914,
+    -869,
+    -825,
+    -781,
+    -737,
+    -694,
+    -652,
+    -610,
+    -568,
+    -527,
+    -486,
+    -446,
+    -406,
+    -367,
+    -328,
+    -289,
+    -250,
+    -212,
+    -175,
+    -137,
+    -100,
+    -63,
+    -27,
+    9,
+    45,
+    81,
+    116,
+    151,
+    186,
+    221,
+    255,
+    289,
+    323,
+    357,
+    391,
+    424,
+    457,
+    490,
+    523,
+    555,
+    588,
+    620,
+    652,
+    684,
+    715,
+    747,
+    778,
+    810,
+    841,
+    872,
+    903,
+    933,
+    964,
+    994,
+    1025,
+    1055,
+    1085,
+    1115,
+    1145,
+    1174,
+    1204,
+    1233,
+    1263,
+    1292,
+    1321,
+    1351,
+    1380,
+    1409,
+    1437,
+    1466,
+    1495,
+    1523,
+    1552,
+    1581,
+    1609,
+    1637,
+    1666,
+    1694,
+    1722,
+    1750,
+    1778,
+    1806,
+    1834,
+    1862,
+    1890,
+    1917,
+    1945,
+    1973,
+    2000,
+    2028,
+    2055,
+    2083,
+    2110,
+    2138,
+    2165,
+    2192,
+    2220,
+    2247,
+    2274,
+    2302,
+    2329,
+    2356,
+    2383,
+    2410,
+    2437,
+    2464,
+    2492,
+    2519,
+    2546,
+    2573,
+    2600,
+    2627,
+    2654,
+    2681,
+    2708,
+    2735,
+    2762,
+    2789,
+    2816,
+    2843,
+    2870,
+    2897,
+    2924,
+    2951,
+    2978,
+    3005,
+    3032,
+    3059,
+    3086,
+    3113,
+    3141,
+    3168,
+    3195,
+    3222,
+    3249,
+    3277,
+    3304,
+    3331,
+    3358,
+    3386,
+    3413,
+    3441,
+    3468,
+    3495,
+    3523,
+    3550,
+    3578,
+    3606,
+    3633,
+    3661,
+    3689,
+    3717,
+    3744,
+    3772,
+    3800,
+    3828,
+    3856,
+    3884,
+    3913,
+    3941,
+    3969,
+    3997,
+    4026,
+    4054,
+    4083,
+    4111,
+    4140,
+    4169,
+    4198,
+    4227,
+    4256,
+    4285,
+    4314,
+    4343,
+    4372,
+    4402,
+    4431,
+    4461,
+    4490,
+    4520,
+    4550,
+    4580,
+    4610,
+    4640,
+    4670,
+    4700,
+    4731,
+    4761,
+    4792,
+    4823,
+    4853,
+    4884,
+    4916,
+    4947,
+    4978,
+    5010,
+    5041,
+    5073,
+    5105,
+    5137,
+    5169,
+    5201,
+    5234,
+    5266,
+    5299,
+    5332,
+    5365,
+    5398,
+    5431,
+    5465,
+    5498,
+    5532,
+    5566,
+    5600,
+    5635,
+    5669,
+    5704,
+    5739,
+    5774,
+    5809,
+    5845,
+    5880,
+    5916,
+    5952,
+    5989,
+    6025,
+    6062,
+    6099,
+    6136,
+    6174,
+    6212,
+    6250,
+    6288,
+    6326,
+    6365,
+    6404,
+    6443,
+    6483,
+    6523,
+    6563,
+    6603,
+    6644,
+    6685,
+    6727,
+    6768,
+    6810,
+    6853,
+    6895,
+    6939,
+    6982,
+    7026,
+    7070,
+    7114,
+    7159,
+    7205,
+    7251,
+    7297,
+    7343,
+    7390,
+    7438,
+    7486,
+    7534,
+    7583,
+    7633,
+    7683,
+    7733,
+    7784,
+    7835,
+    7888,
+    7940,
+    7993,
+    8047,
+    8102,
+    8157,
+    8213,
+    8269,
+    8326,
+    8384,
+    8443,
+    8502,
+    8562,
+    8623,
+    8685,
+    8748,
+    8811,
+    8876,
+    8941,
+    9007,
+    9075,
+    9143,
+    9212,
+    9283,
+    9354,
+    9427,
+    9501,
+    9577,
+    9653,
+    9731,
+    9810,
+    9891,
+    9974,
+    10058,
+    10143,
+    10230,
+    10319,
+    10410,
+    10503,
+    10598,
+    10695,
+    10794,
+    10896,
+    10999,
+    11106,
+    11215,
+    11327,
+    11442,
+    11560,
+    11681,
+    11806,
+    11934,
+    12067,
+    12203,
+    12344,
+    12490,
+    12640,
+    12796,
+    12958,
+    13125,
+    13300,
+    13481,
+    13670,
+    13867,
+    14074,
+    14290,
+    14517,
+    14756,
+    15008,
+    15274,
+    15557,
+    15858,
+    16179,
+    16523,
+    16894,
+    17296,
+    17732,
+    18211,
+    18740,
+    19329,
+    19992,
+    20749,
+    21626,
+    22665,
+    23929,
+    25525,
+    27652,
+    30748,
+    32767,
+    32767,
+    32767,
+];
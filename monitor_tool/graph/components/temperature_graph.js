@@ -1,9 +1,16 @@
 //import { he } from 'date-fns/locale';
-import React from 'react';
+import React, { useEffect } from 'react';
 import { Line } from 'react-chartjs-2'
 import styles from 'styles/temperature_graph.module.css'
 
+const SAMPLE_INTERVAL_MS = 1000 * 10;
+
 export default function TemperatureGraph() {
+  useEffect(() => {
+    const source = subscribeTemperature();
+    return () => source.close();
+  }, []);
+
   return (
     <div className={styles.GraphContainer}>
       <div className={styles.Graph}>
@@ -31,7 +38,7 @@ export default function TemperatureGraph() {
                 realtime: {
                   duration: 1000 * 60 * 10,       // 10min
                   delay: -1000 * 60 * 3,
-                  refresh: 1000 * 10,
+                  refresh: SAMPLE_INTERVAL_MS,
                   pause: false,
                   onRefresh: refreshGraph
                 }
@@ -48,44 +55,35 @@ export default function TemperatureGraph() {
   )
 }
 
-async function refreshGraph(chart) {
-  let temperature = await getTemperature();
-  if( temperature == null ){
-    return;
-  }
+// Samples pushed by event stream, moved to chart on next refresh.
+let samples = [];
 
-  let [cpu_temp, heater_temp] = temperature;
-  //console.log('cputemp:%d, heatertemp:%d', cpu_temp, heater_temp);
-  
-  let date = Date.now();
-  chart.data.datasets[0].data.push({
-    x: date,
-    y: cpu_temp,
-  });
-  chart.data.datasets[1].data.push({
-    x: date,
-    y: heater_temp,
-  });
+function refreshGraph(chart) {
+  for( const [date, cpu_temp, heater_temp] of samples ){
+    //console.log('cputemp:%d, heatertemp:%d', cpu_temp, heater_temp);
+    chart.data.datasets[0].data.push({
+      x: date,
+      y: cpu_temp,
+    });
+    chart.data.datasets[1].data.push({
+      x: date,
+      y: heater_temp,
+    });
+  }
+  samples = [];
 }
 
-async function getTemperature()
+function subscribeTemperature()
 {
-  const json = await fetch('http://192.168.24.107/details')
-  .then( response => {
-    if( response.ok ){
-      return response.json();
-    }
-    else {
-      return null;
-    }
-  })
-  .catch(error => {
-    console.error('通信に失敗しました', error);
+  // EventSource reconnects automatically when connection is lost.
+  const source = new EventSource('http://192.168.24.107/events/stream?interval=' + SAMPLE_INTERVAL_MS);
+  source.addEventListener('temperature', event => {
+    const json = JSON.parse(event.data);
+    samples.push([Date.now(), json.cpu_temp[0], json.heater_temp[0]]);
   });
+  source.onerror = error => {
+    console.error('通信に失敗しました', error);
+  };
 
-  if( json != null ){
-    return [json.cpu_temp[0], json.heater_temp[0]];
-  }
-
-  return null;
-}
\ No newline at end of file
+  return source;
+}
@@ -0,0 +1,48 @@
+// Firmware modules are compiled for host, so their tests run with "cargo test" in this directory.
+// Only modules independent of RP2040 peripherals are included, others are replaced by stubs below.
+// Firmware items unused by tests are dead code on host, and firmware writes "field: field", so lints are allowed per module.
+extern crate alloc;
+
+#[path = "../../../appsrc/src/temperature.rs"]
+// FloatCore is needed without std only, "to_unit(&self)".
+#[allow(dead_code, unused_imports, clippy::wrong_self_convention)]
+mod temperature;
+#[path = "../../../appsrc/src/sensor.rs"]
+#[allow(dead_code)]
+mod sensor;
+#[path = "../../../appsrc/src/max31855.rs"]
+#[allow(dead_code, clippy::redundant_field_names)]
+mod max31855;
+#[path = "../../../appsrc/src/websocket.rs"]
+#[allow(dead_code, clippy::redundant_field_names)]
+mod websocket;
+#[path = "../../../appsrc/src/sht3x.rs"]
+#[allow(dead_code, clippy::redundant_field_names)]
+mod sht3x;
+// Conversion table generator of firmware build script.
+#[path = "../../../appsrc/build.rs"]
+#[allow(dead_code, clippy::redundant_field_names)]
+mod build;
+
+// Flash is not available on host.
+#[allow(dead_code)]
+mod storage
+{
+    use alloc::string::String;
+
+    #[derive(Copy, Clone)]
+    pub enum StorageSlot
+    {
+        TemperatureUnit,
+    }
+
+    pub fn storage_read(_slot: StorageSlot, _buf: &mut [u8]) -> Result<usize, String>
+    {
+        Err(String::from("no storage on host"))
+    }
+
+    pub fn storage_write(_slot: StorageSlot, _data: &[u8]) -> Result<(), String>
+    {
+        Ok(())
+    }
+}